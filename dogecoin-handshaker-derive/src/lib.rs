@@ -0,0 +1,238 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, LitInt};
+
+// Field encodings understood by #[wire(...)]
+enum Encoding {
+    Default,
+    BigEndian,
+    VarStr,
+    CompactVec,
+}
+
+struct FieldAttributes {
+    encoding: Encoding,
+    optional_since: Option<LitInt>,
+    // Protocol limit on a compact_vec count, TooManyEntries both ways when exceeded
+    max: Option<Expr>,
+}
+
+#[proc_macro_derive(NetworkSerializable, attributes(wire))]
+pub fn derive_network_serializable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "NetworkSerializable can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "NetworkSerializable can only be derived for structs",
+            ))
+        }
+    };
+
+    let allow_trailing = parse_struct_attributes(&input)?;
+    let serializer = quote!(::dogecoin_handshaker::dogecoin::serializer);
+    let errors = quote!(::dogecoin_handshaker::dogecoin::errors);
+
+    let mut decode_statements = Vec::new();
+    let mut encode_statements = Vec::new();
+    let mut field_names = Vec::new();
+    let mut has_protocol_version = false;
+
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap();
+        let attributes = parse_field_attributes(field)?;
+
+        let decode = match attributes.encoding {
+            Encoding::Default => quote!(reader.read()?),
+            Encoding::BigEndian => quote!(#serializer::BigEndian::decode_be(reader)?),
+            Encoding::VarStr => quote!(reader.read_var_str()?),
+            Encoding::CompactVec => match &attributes.max {
+                Some(max) => quote!(reader.read_compact_vec_capped(#max)?),
+                None => quote!(reader.read_compact_vec()?),
+            },
+        };
+        let encode = |value: TokenStream2| match (&attributes.encoding, &attributes.max) {
+            (Encoding::Default, _) => quote!(#serializer::Encode::encode(#value, buffer)?;),
+            (Encoding::BigEndian, _) => quote!(#serializer::BigEndian::encode_be(#value, buffer);),
+            (Encoding::VarStr, _) => quote!(#serializer::encode_var_str(#value, buffer)?;),
+            (Encoding::CompactVec, None) => {
+                quote!(#serializer::encode_compact_vec(#value, buffer)?;)
+            }
+            (Encoding::CompactVec, Some(max)) => quote! {
+                if (#value).len() > #max {
+                    return Err(#errors::NetworkSerializationError::TooManyEntries {
+                        count: (#value).len() as u64,
+                        limit: (#max) as u64,
+                    });
+                }
+                #serializer::encode_compact_vec(#value, buffer)?;
+            },
+        };
+
+        match &attributes.optional_since {
+            None => {
+                decode_statements.push(quote!(let #field_name = #decode;));
+                encode_statements.push(encode(quote!(&self.#field_name)));
+            }
+            Some(version) => {
+                // Fields added in later protocol versions may be missing from older peers
+                if !has_protocol_version {
+                    return Err(Error::new_spanned(
+                        field,
+                        "optional_since requires a preceding `protocol_version` field",
+                    ));
+                }
+                decode_statements.push(quote! {
+                    let #field_name = if protocol_version >= #version && !reader.is_empty() {
+                        Some(#decode)
+                    } else {
+                        None
+                    };
+                });
+                let encode_value = encode(quote!(value));
+                encode_statements.push(quote! {
+                    if let Some(value) = &self.#field_name {
+                        if self.protocol_version >= #version {
+                            #encode_value
+                        }
+                    }
+                });
+            }
+        }
+
+        if field_name == "protocol_version" {
+            has_protocol_version = true;
+        }
+        field_names.push(field_name);
+    }
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #serializer::Decode for #name #type_generics #where_clause {
+            fn decode(
+                reader: &mut #serializer::Reader,
+            ) -> ::std::result::Result<Self, #errors::NetworkSerializationError> {
+                #(#decode_statements)*
+
+                Ok(Self { #(#field_names),* })
+            }
+        }
+
+        impl #impl_generics #serializer::Encode for #name #type_generics #where_clause {
+            fn encode(
+                &self,
+                buffer: &mut ::std::vec::Vec<u8>,
+            ) -> ::std::result::Result<(), #errors::NetworkSerializationError> {
+                #(#encode_statements)*
+
+                Ok(())
+            }
+        }
+
+        impl #impl_generics ::dogecoin_handshaker::dogecoin::NetworkSerializable<#name #type_generics>
+            for #name #type_generics #where_clause
+        {
+            fn from_network_bytes(
+                bytes: &[u8],
+            ) -> ::std::result::Result<Self, #errors::NetworkSerializationError> {
+                let mut reader = #serializer::Reader::new(bytes);
+                let value = reader.read()?;
+                if !#allow_trailing && !reader.is_empty() {
+                    return Err(#errors::NetworkSerializationError::UnknownBytes);
+                }
+
+                Ok(value)
+            }
+
+            fn to_network_bytes(
+                &self,
+            ) -> ::std::result::Result<::std::vec::Vec<u8>, #errors::NetworkSerializationError>
+            {
+                let mut buffer = ::std::vec::Vec::new();
+                #serializer::Encode::encode(self, &mut buffer)?;
+
+                Ok(buffer)
+            }
+        }
+    })
+}
+
+// #[wire(allow_trailing)] on the struct ignores leftover payload, like Core does for some messages
+fn parse_struct_attributes(input: &DeriveInput) -> Result<bool, Error> {
+    let mut allow_trailing = false;
+    for attribute in input.attrs.iter().filter(|a| a.path().is_ident("wire")) {
+        attribute.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("allow_trailing") {
+                return Err(meta.error("unknown wire attribute"));
+            }
+            allow_trailing = true;
+
+            Ok(())
+        })?;
+    }
+
+    Ok(allow_trailing)
+}
+
+fn parse_field_attributes(field: &syn::Field) -> Result<FieldAttributes, Error> {
+    let mut attributes = FieldAttributes {
+        encoding: Encoding::Default,
+        optional_since: None,
+        max: None,
+    };
+
+    for attribute in field.attrs.iter().filter(|a| a.path().is_ident("wire")) {
+        attribute.parse_nested_meta(|meta| {
+            let encoding = if meta.path.is_ident("be") {
+                Encoding::BigEndian
+            } else if meta.path.is_ident("var_str") {
+                Encoding::VarStr
+            } else if meta.path.is_ident("compact_vec") {
+                Encoding::CompactVec
+            } else if meta.path.is_ident("optional_since") {
+                attributes.optional_since = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else if meta.path.is_ident("max") {
+                attributes.max = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else {
+                return Err(meta.error("unknown wire attribute"));
+            };
+
+            if !matches!(attributes.encoding, Encoding::Default) {
+                return Err(meta.error("only one wire encoding can be set per field"));
+            }
+            attributes.encoding = encoding;
+
+            Ok(())
+        })?;
+    }
+
+    if attributes.max.is_some() && !matches!(attributes.encoding, Encoding::CompactVec) {
+        return Err(Error::new_spanned(
+            field,
+            "max only applies to compact_vec fields",
+        ));
+    }
+
+    Ok(attributes)
+}
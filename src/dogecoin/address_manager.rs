@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::SystemTime;
+
+use bitcoin_hashes::{sha256, Hash};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::messages::addr::TimestampedIpData;
+use super::messages::version::IpData;
+use super::service_flags::ServiceFlags;
+
+// Peer address book after Dogecoin Core's addrman, heard-of addresses go to the new table and
+// move to the tried table once a handshake with them succeeded
+
+pub const NEW_BUCKET_COUNT: usize = 1024;
+pub const TRIED_BUCKET_COUNT: usize = 256;
+pub const BUCKET_SIZE: usize = 64;
+
+// Group spread limits, ADDRMAN_*_BUCKETS_PER_*_GROUP in Core
+const NEW_BUCKETS_PER_SOURCE_GROUP: u64 = 64;
+const TRIED_BUCKETS_PER_GROUP: u64 = 8;
+
+const MINUTE: u64 = 60;
+const DAY: u64 = 24 * 60 * MINUTE;
+
+// IsTerrible thresholds from Core
+const HORIZON: u64 = 30 * DAY;
+const RETRIES: u32 = 3;
+const MAX_FAILURES: u32 = 10;
+const MIN_FAIL: u64 = 7 * DAY;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AddressInfo {
+    pub address: SocketAddr,
+    pub services: ServiceFlags,
+    pub source: IpAddr,
+    pub last_seen: u64,
+    pub last_try: Option<u64>,
+    pub last_success: Option<u64>,
+    pub attempts: u32,
+    pub in_tried: bool,
+    slot: (usize, usize),
+}
+
+impl AddressInfo {
+    fn is_terrible(&self, now: u64) -> bool {
+        if self
+            .last_try
+            .is_some_and(|last_try| now < last_try + MINUTE)
+        {
+            return false;
+        }
+
+        self.last_seen > now + 10 * MINUTE
+            || self.last_seen + HORIZON < now
+            || (self.last_success.is_none() && self.attempts >= RETRIES)
+            || (self
+                .last_success
+                .is_some_and(|last_success| last_success + MIN_FAIL < now)
+                && self.attempts >= MAX_FAILURES)
+    }
+
+    fn chance(&self, now: u64) -> f64 {
+        let mut chance = 0.66f64.powi(self.attempts.min(8) as i32);
+        if self
+            .last_try
+            .is_some_and(|last_try| now < last_try + 10 * MINUTE)
+        {
+            chance *= 0.01;
+        }
+
+        chance
+    }
+}
+
+pub struct AddressManager {
+    entries: HashMap<SocketAddr, AddressInfo>,
+    key: [u8; 32],
+    new_table: HashMap<(usize, usize), SocketAddr>,
+    random_number_generator: StdRng,
+    tried_table: HashMap<(usize, usize), SocketAddr>,
+}
+
+impl Default for AddressManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AddressManager {
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    // Same seed -> same bucketing and selection, meant for tests and simulations
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(mut random_number_generator: StdRng) -> Self {
+        Self {
+            entries: HashMap::new(),
+            key: random_number_generator.gen(),
+            new_table: HashMap::new(),
+            random_number_generator,
+            tried_table: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn new_count(&self) -> usize {
+        self.new_table.len()
+    }
+
+    pub fn tried_count(&self) -> usize {
+        self.tried_table.len()
+    }
+
+    pub fn get(&self, address: &SocketAddr) -> Option<&AddressInfo> {
+        self.entries.get(address)
+    }
+
+    // Returns true when the address was not known before
+    pub fn add(&mut self, address: &TimestampedIpData, source: IpAddr) -> bool {
+        self.add_at(address, source, unix_now())
+    }
+
+    pub fn attempt(&mut self, address: &SocketAddr) {
+        self.attempt_at(address, unix_now())
+    }
+
+    pub fn good(&mut self, address: &SocketAddr, services: ServiceFlags) {
+        self.good_at(address, services, unix_now())
+    }
+
+    // Tried and new tables get equal odds however big they are, then entries that failed
+    // recently are less likely to come up, see CAddrMan::Select_
+    pub fn select(&mut self) -> Option<AddressInfo> {
+        let now = unix_now();
+        let use_tried = match (self.tried_table.is_empty(), self.new_table.is_empty()) {
+            (true, true) => return None,
+            (false, true) => true,
+            (true, false) => false,
+            (false, false) => self.random_number_generator.gen_bool(0.5),
+        };
+        let table = if use_tried {
+            &self.tried_table
+        } else {
+            &self.new_table
+        };
+        let candidates: Vec<SocketAddr> = table.values().copied().collect();
+
+        let mut chance_factor = 1.0;
+        loop {
+            let index = self.random_number_generator.gen_range(0..candidates.len());
+            let entry = &self.entries[&candidates[index]];
+            if self.random_number_generator.gen::<f64>() < chance_factor * entry.chance(now) {
+                return Some(entry.clone());
+            }
+            chance_factor *= 1.2;
+        }
+    }
+
+    fn add_at(&mut self, address: &TimestampedIpData, source: IpAddr, now: u64) -> bool {
+        if !is_routable(&address.ip_data.ip_address) || address.ip_data.port == 0 {
+            return false;
+        }
+
+        self.insert_at(address, source, now)
+    }
+
+    fn insert_at(&mut self, address: &TimestampedIpData, source: IpAddr, now: u64) -> bool {
+        let socket_address = SocketAddr::new(address.ip_data.ip_address, address.ip_data.port);
+
+        if let Some(entry) = self.entries.get_mut(&socket_address) {
+            entry.last_seen = entry.last_seen.max(address.timestamp as u64);
+            entry.services.insert(address.ip_data.node_services);
+            return false;
+        }
+
+        let slot = self.new_slot(&socket_address, &source);
+        if let Some(occupant) = self.new_table.get(&slot).copied() {
+            if !self.entries[&occupant].is_terrible(now) {
+                return false;
+            }
+            self.entries.remove(&occupant);
+        }
+
+        self.new_table.insert(slot, socket_address);
+        self.entries.insert(
+            socket_address,
+            AddressInfo {
+                address: socket_address,
+                services: address.ip_data.node_services,
+                source,
+                last_seen: address.timestamp as u64,
+                last_try: None,
+                last_success: None,
+                attempts: 0,
+                in_tried: false,
+                slot,
+            },
+        );
+
+        true
+    }
+
+    fn attempt_at(&mut self, address: &SocketAddr, now: u64) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            entry.last_try = Some(now);
+            entry.attempts += 1;
+        }
+    }
+
+    fn good_at(&mut self, address: &SocketAddr, services: ServiceFlags, now: u64) {
+        if !self.entries.contains_key(address) {
+            let ip_data = TimestampedIpData {
+                timestamp: now as u32,
+                ip_data: IpData {
+                    node_services: services,
+                    ip_address: address.ip(),
+                    port: address.port(),
+                },
+            };
+            // We just reached it, so it's kept even when it isn't routable, like a local node
+            if !self.insert_at(&ip_data, address.ip(), now) {
+                return;
+            }
+        }
+
+        let entry = self.entries.get_mut(address).unwrap();
+        entry.services = services;
+        entry.last_seen = now;
+        entry.last_try = Some(now);
+        entry.last_success = Some(now);
+        entry.attempts = 0;
+        if entry.in_tried {
+            return;
+        }
+
+        self.new_table.remove(&entry.slot);
+        let slot = self.tried_slot(address);
+
+        // The previous occupant goes back to the new table, same as Core's MakeTried
+        if let Some(evicted) = self.tried_table.insert(slot, *address) {
+            let evicted_slot = {
+                let entry = &self.entries[&evicted];
+                self.new_slot(&evicted, &entry.source)
+            };
+            if let Some(occupant) = self.new_table.insert(evicted_slot, evicted) {
+                self.entries.remove(&occupant);
+            }
+            let entry = self.entries.get_mut(&evicted).unwrap();
+            entry.in_tried = false;
+            entry.slot = evicted_slot;
+        }
+
+        let entry = self.entries.get_mut(address).unwrap();
+        entry.in_tried = true;
+        entry.slot = slot;
+    }
+
+    fn new_slot(&self, address: &SocketAddr, source: &IpAddr) -> (usize, usize) {
+        let group = network_group(&address.ip());
+        let source_group = network_group(source);
+        let spread = self.hash(&[&group, &source_group]) % NEW_BUCKETS_PER_SOURCE_GROUP;
+        let bucket = self.hash(&[&source_group, &spread.to_le_bytes()]) as usize % NEW_BUCKET_COUNT;
+
+        (bucket, self.position(b'N', bucket, address))
+    }
+
+    fn tried_slot(&self, address: &SocketAddr) -> (usize, usize) {
+        let group = network_group(&address.ip());
+        let spread = self.hash(&[&address_key(address)]) % TRIED_BUCKETS_PER_GROUP;
+        let bucket = self.hash(&[&group, &spread.to_le_bytes()]) as usize % TRIED_BUCKET_COUNT;
+
+        (bucket, self.position(b'K', bucket, address))
+    }
+
+    fn position(&self, table: u8, bucket: usize, address: &SocketAddr) -> usize {
+        let hash = self.hash(&[
+            &[table],
+            &(bucket as u64).to_le_bytes(),
+            &address_key(address),
+        ]);
+
+        hash as usize % BUCKET_SIZE
+    }
+
+    fn hash(&self, parts: &[&[u8]]) -> u64 {
+        let mut data = self.key.to_vec();
+        for part in parts {
+            data.extend_from_slice(part);
+        }
+        let hash = sha256::Hash::hash(&data).into_inner();
+
+        u64::from_le_bytes(hash[0..8].try_into().unwrap())
+    }
+}
+
+// Same ranges as CNetAddr::IsRoutable, gossip about these is useless to anyone but the sender
+pub fn is_routable(ip: &IpAddr) -> bool {
+    let ipv4 = match ip {
+        IpAddr::V4(ipv4) => Some(*ipv4),
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped(),
+    };
+
+    match (ipv4, ip) {
+        (Some(ipv4), _) => {
+            let [a, b, c, _] = ipv4.octets();
+            !(a == 0
+                || ipv4.is_loopback()
+                || ipv4.is_private()
+                || ipv4.is_link_local()
+                || ipv4.is_broadcast()
+                || (a == 100 && b & 0xc0 == 64)
+                || (a == 198 && b & 0xfe == 18)
+                || matches!((a, b, c), (192, 0, 2) | (198, 51, 100) | (203, 0, 113)))
+        }
+        (None, IpAddr::V6(ipv6)) => {
+            let segments = ipv6.segments();
+            !(ipv6.is_unspecified()
+                || ipv6.is_loopback()
+                || segments[..4] == [0xfe80, 0, 0, 0]
+                || segments[0] & 0xfe00 == 0xfc00
+                || (segments[0] == 0x2001 && segments[1] & 0xfff0 == 0x0010)
+                || (segments[0] == 0x2001 && segments[1] & 0xfff0 == 0x0020)
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+        }
+        (None, IpAddr::V4(_)) => unreachable!(),
+    }
+}
+
+// Addresses from one /16 (IPv4) or /32 (IPv6) are likely run by the same operator,
+// everything non-routable shares one group like NET_UNROUTABLE in Core
+pub fn network_group(ip: &IpAddr) -> Vec<u8> {
+    if !is_routable(ip) {
+        return vec![0];
+    }
+
+    let ipv4 = match ip {
+        IpAddr::V4(ipv4) => Some(*ipv4),
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped(),
+    };
+
+    match (ipv4, ip) {
+        (Some(ipv4), _) => vec![4, ipv4.octets()[0], ipv4.octets()[1]],
+        (None, IpAddr::V6(ipv6)) => [&[6], &ipv6.octets()[0..4]].concat(),
+        (None, IpAddr::V4(_)) => unreachable!(),
+    }
+}
+
+fn address_key(address: &SocketAddr) -> Vec<u8> {
+    let ip = match address.ip() {
+        IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
+        IpAddr::V6(ipv6) => ipv6,
+    };
+
+    [&ip.octets()[..], &address.port().to_be_bytes()].concat()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_681_336_434;
+
+    fn timestamped(address: &str, timestamp: u64) -> TimestampedIpData {
+        let address: SocketAddr = address.parse().unwrap();
+        TimestampedIpData {
+            timestamp: timestamp as u32,
+            ip_data: IpData {
+                node_services: ServiceFlags::NODE_NETWORK,
+                ip_address: address.ip(),
+                port: address.port(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_network_group() {
+        assert_eq!(
+            vec![4, 52, 77],
+            network_group(&"52.77.231.41".parse().unwrap())
+        );
+        assert_eq!(
+            network_group(&"52.77.1.1".parse().unwrap()),
+            network_group(&"::ffff:52.77.231.41".parse().unwrap())
+        );
+        assert_eq!(
+            vec![6, 0x2a, 0x01, 0x04, 0xf8],
+            network_group(&"2a01:4f8::1".parse().unwrap())
+        );
+        for ip in [
+            "192.168.1.1",
+            "100.64.0.1",
+            "169.254.1.1",
+            "::1",
+            "fd00::1",
+            "2001:db8::1",
+        ] {
+            assert_eq!(vec![0], network_group(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_is_routable() {
+        for ip in [
+            "52.77.231.41",
+            "::ffff:52.77.231.41",
+            "2a01:4f8::1",
+            "100.128.0.1",
+        ] {
+            assert!(is_routable(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "0.0.0.0",
+            "0.1.2.3",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.1.1",
+            "100.64.0.1",
+            "198.19.255.255",
+            "192.0.2.1",
+            "198.51.100.1",
+            "203.0.113.1",
+            "255.255.255.255",
+            "::ffff:192.168.1.1",
+            "::",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "2001:10::1",
+            "2001:20::1",
+            "2001:db8::1",
+        ] {
+            assert!(!is_routable(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_add_and_good() {
+        let mut manager = AddressManager::with_seed(1);
+        let source = "52.77.231.41".parse().unwrap();
+
+        assert!(manager.add_at(&timestamped("1.2.3.4:22556", NOW - 100), source, NOW));
+        assert!(!manager.add_at(&timestamped("1.2.3.4:22556", NOW), source, NOW));
+        assert!(!manager.add_at(&timestamped("0.0.0.0:22556", NOW), source, NOW));
+        assert!(!manager.add_at(&timestamped("192.168.1.1:22556", NOW), source, NOW));
+        assert!(!manager.add_at(&timestamped("[::1]:22556", NOW), source, NOW));
+        assert_eq!(1, manager.len());
+        assert_eq!(1, manager.new_count());
+
+        let address = "1.2.3.4:22556".parse().unwrap();
+        assert_eq!(NOW, manager.get(&address).unwrap().last_seen);
+
+        manager.attempt_at(&address, NOW);
+        assert_eq!(1, manager.get(&address).unwrap().attempts);
+
+        manager.good_at(&address, ServiceFlags::NODE_BLOOM, NOW + 1);
+        let entry = manager.get(&address).unwrap();
+        assert!(entry.in_tried);
+        assert_eq!(0, entry.attempts);
+        assert_eq!(Some(NOW + 1), entry.last_success);
+        assert_eq!(ServiceFlags::NODE_BLOOM, entry.services);
+        assert_eq!((0, 1), (manager.new_count(), manager.tried_count()));
+
+        // Unknown addresses that completed a handshake go straight to tried
+        let address = "5.6.7.8:22556".parse().unwrap();
+        manager.good_at(&address, ServiceFlags::NODE_NETWORK, NOW);
+        assert!(manager.get(&address).unwrap().in_tried);
+    }
+
+    #[test]
+    fn test_terrible_entries_are_replaced() {
+        let mut manager = AddressManager::with_seed(2);
+        let source = "52.77.231.41".parse().unwrap();
+        assert!(manager.add_at(&timestamped("1.2.3.4:22556", NOW), source, NOW));
+
+        // Same source group spreads over a limited set of buckets, fill one slot up
+        let address: SocketAddr = "1.2.3.4:22556".parse().unwrap();
+        let slot = manager.get(&address).unwrap().slot;
+        let colliding = (1..u16::MAX)
+            .map(|port| SocketAddr::new(address.ip(), port))
+            .find(|candidate| *candidate != address && manager.new_slot(candidate, &source) == slot)
+            .unwrap();
+
+        let entry = timestamped(&colliding.to_string(), NOW);
+        assert!(!manager.add_at(&entry, source, NOW));
+
+        for _ in 0..RETRIES {
+            manager.attempt_at(&address, NOW - 2 * MINUTE);
+        }
+        assert!(manager.add_at(&entry, source, NOW));
+        assert!(manager.get(&address).is_none());
+    }
+
+    #[test]
+    fn test_select() {
+        let mut manager = AddressManager::with_seed(3);
+        assert_eq!(None, manager.select());
+
+        let source = "52.77.231.41".parse().unwrap();
+        let now = unix_now();
+        for index in 0..100 {
+            let address = format!("11.{}.{}.1:22556", index, index);
+            manager.add_at(&timestamped(&address, now), source, now);
+        }
+        let tried = "1.2.3.4:22556".parse().unwrap();
+        manager.good(&tried, ServiceFlags::NODE_NETWORK);
+
+        let tried_selections = (0..1000)
+            .filter(|_| manager.select().unwrap().address == tried)
+            .count();
+        assert!(tried_selections > 300, "{}", tried_selections);
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::*;
+use connection_config::ConnectionConfig;
+use errors::*;
+use frame::{decode_frame, RECEIVE_CHUNK_SIZE};
+use handshake::{Handshake, HandshakeAction, HandshakeOutcome};
+use messages::{pong::Pong, Message};
+
+pub struct AsyncNodeConnectionAgent {
+    config: ConnectionConfig,
+    network: NetworkParams,
+    peer_address: SocketAddr,
+    pending_messages: VecDeque<Message>,
+    receive_buffer: Vec<u8>,
+    stream: TcpStream,
+}
+
+impl AsyncNodeConnectionAgent {
+    pub async fn new(
+        network: NetworkParams,
+        host: &str,
+        port: u16,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::new_with_config(network, host, port, config).await
+    }
+
+    pub async fn new_with_config(
+        network: NetworkParams,
+        host: &str,
+        port: u16,
+        config: ConnectionConfig,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let stream = with_timeout(
+            config.connect_timeout,
+            TimeoutPhase::Connect,
+            TcpStream::connect((host, port)),
+        )
+        .await?;
+
+        Self::from_stream_with_config(network, stream, config)
+    }
+
+    pub async fn connect(
+        network: NetworkParams,
+        address: SocketAddr,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::connect_with_config(network, address, config).await
+    }
+
+    pub async fn connect_with_config(
+        network: NetworkParams,
+        address: SocketAddr,
+        config: ConnectionConfig,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let stream = with_timeout(
+            config.connect_timeout,
+            TimeoutPhase::Connect,
+            TcpStream::connect(address),
+        )
+        .await?;
+
+        Self::from_stream_with_config(network, stream, config)
+    }
+
+    pub fn from_stream(
+        network: NetworkParams,
+        stream: TcpStream,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::from_stream_with_config(network, stream, config)
+    }
+
+    pub fn from_stream_with_config(
+        network: NetworkParams,
+        stream: TcpStream,
+        config: ConnectionConfig,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let peer_address = stream.peer_addr()?;
+
+        Ok(Self {
+            config,
+            network,
+            peer_address,
+            pending_messages: VecDeque::new(),
+            receive_buffer: Vec::new(),
+            stream,
+        })
+    }
+
+    pub fn peer_address(&self) -> SocketAddr {
+        self.peer_address
+    }
+
+    pub async fn handshake(&mut self) -> Result<HandshakeOutcome, NodeConnectionAgentError> {
+        let handshake = Handshake::new(self.config.version.get_protocol_version());
+        let handshake_timeout = self.config.handshake_timeout;
+
+        with_timeout(handshake_timeout, TimeoutPhase::Handshake, async {
+            self.send_version().await?;
+            self.run_handshake(handshake).await
+        })
+        .await
+    }
+
+    pub async fn accept_handshake(&mut self) -> Result<HandshakeOutcome, NodeConnectionAgentError> {
+        let handshake = Handshake::new_responder(self.config.version.get_protocol_version());
+        let handshake_timeout = self.config.handshake_timeout;
+
+        with_timeout(
+            handshake_timeout,
+            TimeoutPhase::Handshake,
+            self.run_handshake(handshake),
+        )
+        .await
+    }
+
+    pub async fn read_message(&mut self) -> Result<Message, NodeConnectionAgentError> {
+        if let Some(message) = self.pending_messages.pop_front() {
+            return Ok(message);
+        }
+
+        loop {
+            if let Some(message) = decode_frame(
+                &mut self.receive_buffer,
+                &self.network,
+                self.config.max_message_size,
+                self.config.verify_checksums,
+            )? {
+                return Ok(message);
+            }
+            self.receive_more().await?;
+        }
+    }
+
+    pub async fn write_message(
+        &mut self,
+        message: &Message,
+    ) -> Result<(), NodeConnectionAgentError> {
+        let bytes = message.to_network_bytes(&self.network)?;
+        with_timeout(
+            self.config.write_timeout,
+            TimeoutPhase::Write,
+            self.stream.write_all(bytes.as_slice()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn send_version(&mut self) -> Result<(), NodeConnectionAgentError> {
+        let data = self.config.version.build_for_peer(self.peer_address)?;
+
+        self.write_message(&Message::Version(data)).await
+    }
+
+    async fn run_handshake(
+        &mut self,
+        mut handshake: Handshake,
+    ) -> Result<HandshakeOutcome, NodeConnectionAgentError> {
+        while !handshake.is_complete() {
+            let message = self.read_message().await?;
+            match handshake.on_message(message)? {
+                HandshakeAction::None => {}
+                HandshakeAction::SendPong(nonce) => {
+                    self.write_message(&Message::Pong(Pong { nonce })).await?
+                }
+                HandshakeAction::SendVerack => self.write_message(&Message::Verack).await?,
+                HandshakeAction::SendVersionAndVerack => {
+                    self.send_version().await?;
+                    self.write_message(&Message::Verack).await?
+                }
+            }
+        }
+
+        let (outcome, buffered_messages) = handshake.finish()?;
+        self.pending_messages.extend(buffered_messages);
+
+        Ok(outcome)
+    }
+
+    async fn receive_more(&mut self) -> Result<(), NodeConnectionAgentError> {
+        let mut chunk = [0; RECEIVE_CHUNK_SIZE];
+        let read_size = with_timeout(
+            self.config.read_timeout,
+            TimeoutPhase::Read,
+            self.stream.read(&mut chunk),
+        )
+        .await?;
+        if read_size == 0 {
+            return Err(NodeConnectionAgentError::ConnectionClosed);
+        }
+        self.receive_buffer.extend_from_slice(&chunk[0..read_size]);
+
+        Ok(())
+    }
+}
+
+async fn with_timeout<T, E, F>(
+    timeout: Option<Duration>,
+    phase: TimeoutPhase,
+    future: F,
+) -> Result<T, NodeConnectionAgentError>
+where
+    F: Future<Output = Result<T, E>>,
+    NodeConnectionAgentError: From<E>,
+{
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| NodeConnectionAgentError::Timeout { phase })?,
+        None => future.await,
+    };
+
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::version::VersionMessageData;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_handshake_with_fake_node() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let node = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut node =
+                AsyncNodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+
+            let message = node.read_message().await.unwrap();
+            assert_eq!("version", message.command());
+
+            let data =
+                VersionMessageData::from_network_bytes(&crate::dogecoin::tests::VERSION_MESSAGE)
+                    .unwrap();
+            let mut bytes = Message::Version(data)
+                .to_network_bytes(&NetworkParams::TEST)
+                .unwrap();
+            bytes.extend(
+                Message::Verack
+                    .to_network_bytes(&NetworkParams::TEST)
+                    .unwrap(),
+            );
+            for chunk in bytes.chunks(5) {
+                node.stream.write_all(chunk).await.unwrap();
+            }
+
+            assert_eq!(Message::Verack, node.read_message().await.unwrap());
+        });
+
+        let mut agent = AsyncNodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", port)
+            .await
+            .unwrap();
+        let outcome = agent.handshake().await.unwrap();
+        assert_eq!(70015, outcome.protocol_version);
+        assert_eq!(
+            Some(b"/Shibetoshi:1.14.6/".as_slice()),
+            outcome.peer_version.client_name.as_deref()
+        );
+
+        node.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_unexpected_command() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut node =
+                AsyncNodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            node.read_message().await.unwrap();
+            node.write_message(&Message::Verack).await.unwrap();
+            node.write_message(&Message::Verack).await.unwrap();
+        });
+
+        let mut agent = AsyncNodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", port)
+            .await
+            .unwrap();
+        let result = agent.handshake().await;
+        assert!(matches!(
+            result,
+            Err(NodeConnectionAgentError::UnexpectedCommand(expected, actual))
+                if expected == "version" && actual == "verack"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_accept_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let node = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut node =
+                AsyncNodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            node.accept_handshake().await.unwrap()
+        });
+
+        let mut agent = AsyncNodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", port)
+            .await
+            .unwrap();
+        let outcome = agent.handshake().await.unwrap();
+        assert_eq!(
+            Some(b"/Shibetoshi:1.14.6/".as_slice()),
+            outcome.peer_version.client_name.as_deref()
+        );
+
+        let node_outcome = node.await.unwrap();
+        assert_eq!(70015, node_outcome.protocol_version);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 1024];
+            while matches!(stream.read(&mut buffer).await, Ok(size) if size > 0) {}
+        });
+
+        let config = ConnectionConfig {
+            handshake_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut agent =
+            AsyncNodeConnectionAgent::connect_with_config(NetworkParams::TEST, address, config)
+                .await
+                .unwrap();
+        assert!(matches!(
+            agent.handshake().await,
+            Err(NodeConnectionAgentError::Timeout {
+                phase: TimeoutPhase::Handshake
+            })
+        ));
+    }
+}
@@ -0,0 +1,523 @@
+use bitcoin_hashes::Hash;
+
+use super::*;
+use hash256::Hash256;
+use pow::Target;
+use serializer::{encode_compact_vec, encode_var_str, CompactSize, Decode, Encode, Reader};
+
+// Block header as hashed for the block id, merge mined blocks carry an AuxPow next to it
+
+pub const BLOCK_HEADER_SIZE: usize = 80;
+
+// Version bit marking merge mined blocks, whose proof of work lives in the parent block
+pub const VERSION_AUXPOW: i32 = 1 << 8;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub prev_block: Hash256,
+    pub merkle_root: Hash256,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    pub fn hash(&self) -> Hash256 {
+        let mut buffer = Vec::with_capacity(BLOCK_HEADER_SIZE);
+        // Encoding a header can't fail, all fields are fixed size
+        let _ = self.encode(&mut buffer);
+
+        Hash256::from_bytes(bitcoin_hashes::sha256d::Hash::hash(&buffer).into_inner())
+    }
+
+    // Dogecoin inherited Litecoin's scrypt(N=1024, r=1, p=1) with the header as password and salt
+    pub fn pow_hash(&self) -> Hash256 {
+        let mut buffer = Vec::with_capacity(BLOCK_HEADER_SIZE);
+        let _ = self.encode(&mut buffer);
+
+        let mut hash = [0; 32];
+        // Both params and output length are valid, scrypt can't fail here
+        let params = scrypt::Params::new(10, 1, 1, hash.len()).expect("valid scrypt params");
+        scrypt::scrypt(&buffer, &buffer, &params, &mut hash).expect("valid output length");
+
+        Hash256::from_bytes(hash)
+    }
+
+    // Merge mined headers are checked against their parent block, see HeaderChain::connect
+    pub fn check_proof_of_work(&self, bits: u32) -> Result<(), ProofOfWorkError> {
+        let target = Target::from_compact(bits)?;
+        let hash = self.pow_hash();
+        if !target.is_met_by(&hash) {
+            return Err(ProofOfWorkError::HashAboveTarget { hash, bits });
+        }
+
+        Ok(())
+    }
+
+    pub fn is_aux_pow(&self) -> bool {
+        self.version & VERSION_AUXPOW != 0
+    }
+
+    pub fn chain_id(&self) -> i32 {
+        self.version >> 16
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        Ok(BlockHeader {
+            version: reader.read_i32_le()?,
+            prev_block: reader.read()?,
+            merkle_root: reader.read()?,
+            time: reader.read_u32_le()?,
+            bits: reader.read_u32_le()?,
+            nonce: reader.read_u32_le()?,
+        })
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        self.version.encode(buffer)?;
+        self.prev_block.encode(buffer)?;
+        self.merkle_root.encode(buffer)?;
+        self.time.encode(buffer)?;
+        self.bits.encode(buffer)?;
+        self.nonce.encode(buffer)
+    }
+}
+
+impl NetworkSerializable<BlockHeader> for BlockHeader {
+    fn from_network_bytes(bytes: &[u8]) -> Result<BlockHeader, NetworkSerializationError> {
+        Reader::new(bytes).read()
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        let mut buffer = Vec::with_capacity(BLOCK_HEADER_SIZE);
+        self.encode(&mut buffer)?;
+
+        Ok(buffer)
+    }
+}
+
+// Marks the chain merkle root in a parent coinbase, pchMergedMiningHeader in Core
+pub const MERGED_MINING_HEADER: [u8; 4] = [0xfa, 0xbe, 0x6d, 0x6d];
+
+// Longest chain merkle branch Core accepts, the tree has at most 2^30 chains
+pub const MAX_CHAIN_BRANCH_LENGTH: usize = 30;
+
+// Merge mining proof, see CAuxPow in Dogecoin Core's auxpow.h
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuxPow {
+    // Parent chain coinbase kept serialized, only its first input script is looked at
+    pub coinbase_tx: Vec<u8>,
+    pub parent_block_hash: Hash256,
+    pub coinbase_branch: Vec<Hash256>,
+    pub coinbase_index: i32,
+    pub chain_branch: Vec<Hash256>,
+    pub chain_index: i32,
+    pub parent_header: BlockHeader,
+}
+
+impl AuxPow {
+    pub fn coinbase_hash(&self) -> Hash256 {
+        Hash256::from_bytes(bitcoin_hashes::sha256d::Hash::hash(&self.coinbase_tx).into_inner())
+    }
+
+    // Same checks as CAuxPow::check, the parent's proof of work is left to the caller
+    pub fn check(&self, aux_block_hash: Hash256, chain_id: i32) -> Result<(), AuxPowError> {
+        if self.coinbase_index != 0 {
+            return Err(AuxPowError::CoinbaseNotFirst(self.coinbase_index));
+        }
+        if self.parent_header.chain_id() == chain_id {
+            return Err(AuxPowError::ParentHasOurChainId(chain_id));
+        }
+        let parent_hash = self.parent_header.hash();
+        if self.parent_block_hash != parent_hash {
+            return Err(AuxPowError::ParentHashMismatch {
+                expected: parent_hash,
+                actual: self.parent_block_hash,
+            });
+        }
+        if self.chain_branch.len() > MAX_CHAIN_BRANCH_LENGTH {
+            return Err(AuxPowError::ChainBranchTooLong(self.chain_branch.len()));
+        }
+
+        let chain_root = check_merkle_branch(aux_block_hash, &self.chain_branch, self.chain_index);
+        let coinbase_root = check_merkle_branch(
+            self.coinbase_hash(),
+            &self.coinbase_branch,
+            self.coinbase_index,
+        );
+        if coinbase_root != self.parent_header.merkle_root {
+            return Err(AuxPowError::CoinbaseNotInParent);
+        }
+
+        // The root is committed to in display order, reversed from how it's hashed
+        let script = coinbase_script(&self.coinbase_tx).ok_or(AuxPowError::MalformedCoinbase)?;
+        let mut root = *chain_root.as_bytes();
+        root.reverse();
+        let root_offset = find(&script, &root).ok_or(AuxPowError::MissingChainRoot)?;
+        match find(&script, &MERGED_MINING_HEADER) {
+            Some(header_offset) => {
+                if find(&script[header_offset + 1..], &MERGED_MINING_HEADER).is_some() {
+                    return Err(AuxPowError::MultipleMergedMiningHeaders);
+                }
+                if header_offset + MERGED_MINING_HEADER.len() != root_offset {
+                    return Err(AuxPowError::ChainRootNotAfterHeader);
+                }
+            }
+            // Legacy coinbases without the header must put the root right at the start
+            None if root_offset > 20 => return Err(AuxPowError::ChainRootTooLate(root_offset)),
+            None => {}
+        }
+
+        let mut reader = Reader::new(&script[root_offset + root.len()..]);
+        let (size, nonce) = match (reader.read_u32_le(), reader.read_u32_le()) {
+            (Ok(size), Ok(nonce)) => (size, nonce),
+            _ => return Err(AuxPowError::MissingSizeAndNonce),
+        };
+        let height = self.chain_branch.len();
+        if size != 1 << height {
+            return Err(AuxPowError::BranchSizeMismatch {
+                size,
+                expected: 1 << height,
+            });
+        }
+        let expected = expected_chain_index(nonce, chain_id, height);
+        if self.chain_index != expected {
+            return Err(AuxPowError::WrongChainIndex {
+                index: self.chain_index,
+                expected,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Folds a merkle branch up to its root, like CheckMerkleBranch in Core
+fn check_merkle_branch(hash: Hash256, branch: &[Hash256], mut index: i32) -> Hash256 {
+    if index == -1 {
+        return Hash256::ZERO;
+    }
+
+    let mut hash = hash;
+    for sibling in branch {
+        let (left, right) = match index & 1 {
+            0 => (hash, *sibling),
+            _ => (*sibling, hash),
+        };
+        let concatenated = [*left.as_bytes(), *right.as_bytes()].concat();
+        hash = Hash256::from_bytes(bitcoin_hashes::sha256d::Hash::hash(&concatenated).into_inner());
+        index >>= 1;
+    }
+
+    hash
+}
+
+// Where a chain sits in the merge mining tree, fixed by the nonce so it can't be ground
+fn expected_chain_index(nonce: u32, chain_id: i32, height: usize) -> i32 {
+    let mut rand = nonce;
+    rand = rand.wrapping_mul(1103515245).wrapping_add(12345);
+    rand = rand.wrapping_add(chain_id as u32);
+    rand = rand.wrapping_mul(1103515245).wrapping_add(12345);
+
+    (rand % (1 << height)) as i32
+}
+
+// First input script of a serialized transaction, the coinbase data for a coinbase
+fn coinbase_script(transaction: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Reader::new(transaction);
+    reader.read_i32_le().ok()?;
+    if reader.read_compact_size().ok()? == 0 {
+        return None;
+    }
+    reader.read_array::<36>().ok()?;
+
+    reader.read_var_bytes().ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl Decode for AuxPow {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        Ok(AuxPow {
+            coinbase_tx: read_transaction(reader)?,
+            parent_block_hash: reader.read()?,
+            coinbase_branch: reader.read_compact_vec()?,
+            coinbase_index: reader.read_i32_le()?,
+            chain_branch: reader.read_compact_vec()?,
+            chain_index: reader.read_i32_le()?,
+            parent_header: reader.read()?,
+        })
+    }
+}
+
+impl Encode for AuxPow {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        buffer.extend_from_slice(&self.coinbase_tx);
+        self.parent_block_hash.encode(buffer)?;
+        encode_compact_vec(&self.coinbase_branch, buffer)?;
+        self.coinbase_index.encode(buffer)?;
+        encode_compact_vec(&self.chain_branch, buffer)?;
+        self.chain_index.encode(buffer)?;
+        self.parent_header.encode(buffer)
+    }
+}
+
+// Walks a legacy (non-witness) transaction and returns it re-serialized
+fn read_transaction(reader: &mut Reader) -> Result<Vec<u8>, NetworkSerializationError> {
+    let mut buffer = Vec::new();
+    reader.read_i32_le()?.encode(&mut buffer)?;
+
+    let input_count = reader.read_compact_size()?;
+    CompactSize(input_count).write(&mut buffer);
+    for _ in 0..input_count {
+        reader.read_array::<36>()?.encode(&mut buffer)?;
+        encode_var_str(&reader.read_var_bytes()?, &mut buffer)?;
+        reader.read_u32_le()?.encode(&mut buffer)?;
+    }
+
+    let output_count = reader.read_compact_size()?;
+    CompactSize(output_count).write(&mut buffer);
+    for _ in 0..output_count {
+        reader.read_i64_le()?.encode(&mut buffer)?;
+        encode_var_str(&reader.read_var_bytes()?, &mut buffer)?;
+    }
+
+    reader.read_u32_le()?.encode(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogecoin::tests::{merge_mine, merge_mine_with_script, MAIN_GENESIS_HEADER};
+
+    #[test]
+    fn test_header_round_trip_and_hash() {
+        let header = MAIN_GENESIS_HEADER;
+        let bytes = header.to_network_bytes().unwrap();
+        assert_eq!(BLOCK_HEADER_SIZE, bytes.len());
+        assert_eq!(header, BlockHeader::from_network_bytes(&bytes).unwrap());
+        assert_eq!(
+            Hash256::from_bytes(NetworkParams::MAIN.genesis_hash),
+            header.hash()
+        );
+        assert!(!header.is_aux_pow());
+
+        assert!(BlockHeader::from_network_bytes(&bytes[..79]).is_err());
+    }
+
+    #[test]
+    fn test_proof_of_work() {
+        let header = MAIN_GENESIS_HEADER;
+        assert_eq!(
+            Hash256::from_hex("0000026f3f7874ca0c251314eaed2d2fcf83d7da3acfaacf59417d485310b448"),
+            header.pow_hash()
+        );
+        assert_eq!(Ok(()), header.check_proof_of_work(header.bits));
+
+        let tampered = BlockHeader {
+            nonce: header.nonce + 1,
+            ..header
+        };
+        assert!(matches!(
+            tampered.check_proof_of_work(tampered.bits),
+            Err(ProofOfWorkError::HashAboveTarget { bits: 0x1e0ffff0, hash })
+                if hash == tampered.pow_hash()
+        ));
+        assert_eq!(
+            Err(ProofOfWorkError::NegativeTarget(0x04923456)),
+            header.check_proof_of_work(0x04923456)
+        );
+    }
+
+    #[test]
+    fn test_aux_pow_round_trip() {
+        let mut bytes = vec![
+            // coinbase: version, one input spending nothing, one output, locktime
+            0x01, 0x00, 0x00, 0x00, 0x01,
+        ];
+        bytes.extend([0x00; 32]);
+        bytes.extend([0xFF; 4]);
+        bytes.extend([0x03, 0xAA, 0xBB, 0xCC, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+        bytes.extend(5_000_000_000i64.to_le_bytes());
+        bytes.extend([0x01, 0x51, 0x00, 0x00, 0x00, 0x00]);
+        let coinbase_size = bytes.len();
+        bytes.extend([0x11; 32]);
+        bytes.push(0x01);
+        bytes.extend([0x22; 32]);
+        bytes.extend([0x00; 4]);
+        bytes.push(0x00);
+        bytes.extend([0x00; 4]);
+        bytes.extend(MAIN_GENESIS_HEADER.to_network_bytes().unwrap());
+
+        let aux_pow = Reader::new(&bytes).read::<AuxPow>().unwrap();
+        assert_eq!(&bytes[..coinbase_size], aux_pow.coinbase_tx.as_slice());
+        assert_eq!(
+            vec![Hash256::from_bytes([0x22; 32])],
+            aux_pow.coinbase_branch
+        );
+        assert!(aux_pow.chain_branch.is_empty());
+        assert_eq!(MAIN_GENESIS_HEADER, aux_pow.parent_header);
+
+        let mut encoded = Vec::new();
+        aux_pow.encode(&mut encoded).unwrap();
+        assert_eq!(bytes, encoded);
+
+        assert!(Reader::new(&bytes[..bytes.len() - 1])
+            .read::<AuxPow>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_merkle_branch() {
+        let leaf = Hash256::from_bytes([0x11; 32]);
+        let sibling = Hash256::from_bytes([0x22; 32]);
+        let pair_hash = |left: Hash256, right: Hash256| {
+            let concatenated = [*left.as_bytes(), *right.as_bytes()].concat();
+            Hash256::from_bytes(bitcoin_hashes::sha256d::Hash::hash(&concatenated).into_inner())
+        };
+
+        assert_eq!(leaf, check_merkle_branch(leaf, &[], 0));
+        assert_eq!(
+            pair_hash(leaf, sibling),
+            check_merkle_branch(leaf, &[sibling], 0)
+        );
+        assert_eq!(
+            pair_hash(sibling, leaf),
+            check_merkle_branch(leaf, &[sibling], 1)
+        );
+        assert_eq!(
+            pair_hash(pair_hash(sibling, leaf), leaf),
+            check_merkle_branch(leaf, &[sibling, leaf], 1)
+        );
+        assert_eq!(Hash256::ZERO, check_merkle_branch(leaf, &[sibling], -1));
+    }
+
+    #[test]
+    fn test_aux_pow_check() {
+        let block = BlockHeader {
+            version: 0x0062_0104,
+            ..MAIN_GENESIS_HEADER
+        };
+        let hash = block.hash();
+        let aux_pow = merge_mine(&block);
+        assert_eq!(Ok(()), aux_pow.check(hash, 0x62));
+
+        assert_eq!(
+            Err(AuxPowError::ParentHasOurChainId(0)),
+            aux_pow.check(hash, 0)
+        );
+        assert_eq!(
+            Err(AuxPowError::MissingChainRoot),
+            aux_pow.check(MAIN_GENESIS_HEADER.hash(), 0x62)
+        );
+
+        let mut not_first = aux_pow.clone();
+        not_first.coinbase_index = 1;
+        assert_eq!(
+            Err(AuxPowError::CoinbaseNotFirst(1)),
+            not_first.check(hash, 0x62)
+        );
+
+        let mut wrong_parent = aux_pow.clone();
+        wrong_parent.parent_block_hash = Hash256::ZERO;
+        assert_eq!(
+            Err(AuxPowError::ParentHashMismatch {
+                expected: aux_pow.parent_header.hash(),
+                actual: Hash256::ZERO,
+            }),
+            wrong_parent.check(hash, 0x62)
+        );
+
+        let mut too_long = aux_pow.clone();
+        too_long.chain_branch = vec![Hash256::ZERO; MAX_CHAIN_BRANCH_LENGTH + 1];
+        assert_eq!(
+            Err(AuxPowError::ChainBranchTooLong(31)),
+            too_long.check(hash, 0x62)
+        );
+
+        let mut not_in_parent = aux_pow.clone();
+        not_in_parent.coinbase_branch = vec![Hash256::ZERO];
+        assert_eq!(
+            Err(AuxPowError::CoinbaseNotInParent),
+            not_in_parent.check(hash, 0x62)
+        );
+    }
+
+    #[test]
+    fn test_aux_pow_coinbase_script() {
+        let block = BlockHeader {
+            version: 0x0062_0104,
+            ..MAIN_GENESIS_HEADER
+        };
+        let hash = block.hash();
+        let mut root = *hash.as_bytes();
+        root.reverse();
+        let size_and_nonce = |size: u32| [size.to_le_bytes(), 0u32.to_le_bytes()].concat();
+        let check = |script: Vec<u8>| merge_mine_with_script(script, 0).check(hash, 0x62);
+
+        // Without the header the root has to come early, after the height push
+        let prefix = [0x03, 0x01, 0x02, 0x03];
+        assert_eq!(
+            Ok(()),
+            check([&prefix[..], &root, &size_and_nonce(1)].concat())
+        );
+        assert_eq!(
+            Err(AuxPowError::ChainRootTooLate(21)),
+            check([&[0x00; 21][..], &root, &size_and_nonce(1)].concat())
+        );
+
+        let header = MERGED_MINING_HEADER;
+        assert_eq!(
+            Err(AuxPowError::MultipleMergedMiningHeaders),
+            check([&header[..], &root, &size_and_nonce(1), &header].concat())
+        );
+        assert_eq!(
+            Err(AuxPowError::ChainRootNotAfterHeader),
+            check([&header[..], &[0x00], &root, &size_and_nonce(1)].concat())
+        );
+        assert_eq!(
+            Err(AuxPowError::MissingSizeAndNonce),
+            check([&header[..], &root, &[0x01, 0x00, 0x00, 0x00]].concat())
+        );
+        assert_eq!(
+            Err(AuxPowError::BranchSizeMismatch {
+                size: 2,
+                expected: 1
+            }),
+            check([&header[..], &root, &size_and_nonce(2)].concat())
+        );
+
+        // Our slot in a two chain tree is fixed by the nonce and chain id
+        let sibling = Hash256::from_bytes([0x33; 32]);
+        let index = expected_chain_index(0, 0x62, 1);
+        for (chain_index, result) in [
+            (index, Ok(())),
+            (
+                index ^ 1,
+                Err(AuxPowError::WrongChainIndex {
+                    index: index ^ 1,
+                    expected: index,
+                }),
+            ),
+        ] {
+            let mut root = *check_merkle_branch(hash, &[sibling], chain_index).as_bytes();
+            root.reverse();
+            let mut aux_pow =
+                merge_mine_with_script([&header[..], &root, &size_and_nonce(2)].concat(), 0);
+            aux_pow.chain_branch = vec![sibling];
+            aux_pow.chain_index = chain_index;
+            assert_eq!(result, aux_pow.check(hash, 0x62));
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use super::frame::DEFAULT_MAX_MESSAGE_SIZE;
+use super::messages::version::VersionBuilder;
+use super::NetworkParams;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionConfig {
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub write_timeout: Option<Duration>,
+    pub handshake_timeout: Option<Duration>,
+    pub max_message_size: usize,
+    // Off only for replaying captured traffic with known bad checksums
+    pub verify_checksums: bool,
+    pub version: VersionBuilder,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        // Connect timeout and inactivity timeout follow Dogecoin Core defaults
+        Self {
+            connect_timeout: Some(Duration::from_secs(5)),
+            read_timeout: Some(Duration::from_secs(20 * 60)),
+            write_timeout: Some(Duration::from_secs(20 * 60)),
+            handshake_timeout: Some(Duration::from_secs(60)),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            verify_checksums: true,
+            version: VersionBuilder::default(),
+        }
+    }
+}
+
+impl ConnectionConfig {
+    // Defaults, speaking the protocol version of the given network
+    pub fn for_network(network: &NetworkParams) -> Self {
+        Self {
+            version: VersionBuilder::new().protocol_version(network.protocol_version),
+            ..Default::default()
+        }
+    }
+}
@@ -1,5 +1,10 @@
 use std::time::SystemTimeError;
 
+use super::hash256::Hash256;
+use super::messages::reject::Reject;
+use super::pow::ChainWork;
+use super::serializer::sanitize_string;
+
 #[derive(Debug, PartialEq)]
 pub enum CalculateSizeOfSerializedStringAndLengthBytesError {
     StringTooLong,
@@ -27,25 +32,60 @@ impl From<CalculateSizeOfSerializedStringAndLengthBytesError> for NetworkSeriali
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompactSizeError {
+    BufferTooShort,
+    NonCanonical(u64),
+    TooLarge(u64),
+}
+
+impl std::fmt::Display for CompactSizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompactSizeError::BufferTooShort => write!(f, "Buffer too short for compact size"),
+            CompactSizeError::NonCanonical(value) => {
+                write!(f, "Non-canonical compact size encoding of {}", value)
+            }
+            CompactSizeError::TooLarge(value) => write!(f, "Compact size too large: {}", value),
+        }
+    }
+}
+
+impl std::error::Error for CompactSizeError {}
+
+impl From<CompactSizeError> for NetworkSerializationError {
+    fn from(value: CompactSizeError) -> Self {
+        NetworkSerializationError::CompactSizeError(value)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum HeaderBuildError {
+    CommandDataAfterPadding { offset: usize },
     CommandIsEmpty,
+    CommandNotPrintable { offset: usize, byte: u8 },
     CommandTooLong,
     MessageSizeParseFailure,
     MessageTooLong(usize),
     TooShort,
-    UnknownNetworkType,
 }
 
 impl std::fmt::Display for HeaderBuildError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            HeaderBuildError::CommandDataAfterPadding { offset } => {
+                write!(f, "Command has data after NUL padding at offset {}", offset)
+            }
             HeaderBuildError::CommandIsEmpty => write!(f, "Command is empty"),
+            HeaderBuildError::CommandNotPrintable { offset, byte } => write!(
+                f,
+                "Command contains non-printable byte 0x{:02x} at offset {}",
+                byte, offset
+            ),
             HeaderBuildError::CommandTooLong => write!(f, "Command too long"),
             HeaderBuildError::MessageTooLong(value) => {
                 write!(f, "Value too big for u32: {}", value)
             }
-            HeaderBuildError::UnknownNetworkType => write!(f, "Unknown network type"),
             HeaderBuildError::MessageSizeParseFailure => write!(f, "Message size parse failure"),
             HeaderBuildError::TooShort => {
                 write!(f, "Passed header is too short (24 bytes are required)")
@@ -63,10 +103,14 @@ impl From<HeaderBuildError> for NetworkSerializationError {
 impl From<NetworkSerializationError> for HeaderBuildError {
     fn from(value: NetworkSerializationError) -> Self {
         match value {
-            NetworkSerializationError::BufferTooShort => HeaderBuildError::TooShort,
+            NetworkSerializationError::BufferTooShort { .. } => HeaderBuildError::TooShort,
             NetworkSerializationError::UnknownBytes => HeaderBuildError::MessageSizeParseFailure,
             NetworkSerializationError::HeaderParseError(e) => e,
             NetworkSerializationError::StringParseError => HeaderBuildError::CommandTooLong,
+            NetworkSerializationError::CompactSizeError(_)
+            | NetworkSerializationError::TooManyEntries { .. }
+            | NetworkSerializationError::AuxPowMismatch
+            | NetworkSerializationError::Custom(_) => HeaderBuildError::MessageSizeParseFailure,
         }
     }
 }
@@ -75,27 +119,280 @@ impl std::error::Error for HeaderBuildError {}
 
 #[derive(Clone, Debug)]
 pub enum NetworkSerializationError {
-    BufferTooShort,
+    BufferTooShort { offset: usize, needed: usize },
     UnknownBytes,
     HeaderParseError(HeaderBuildError),
     StringParseError,
+    CompactSizeError(CompactSizeError),
+    TooManyEntries { count: u64, limit: u64 },
+    AuxPowMismatch,
+    Custom(String),
 }
 
 impl std::fmt::Display for NetworkSerializationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            NetworkSerializationError::BufferTooShort => write!(f, "Buffer too short"),
+            NetworkSerializationError::BufferTooShort { offset, needed } => write!(
+                f,
+                "Buffer too short, needed {} more bytes at offset {}",
+                needed, offset
+            ),
             NetworkSerializationError::UnknownBytes => write!(f, "Unknown bytes"),
             NetworkSerializationError::HeaderParseError(e) => {
                 write!(f, "Header parse error: {}", e)
             }
             NetworkSerializationError::StringParseError => write!(f, "String parse error"),
+            NetworkSerializationError::CompactSizeError(e) => {
+                write!(f, "Compact size error: {}", e)
+            }
+            NetworkSerializationError::TooManyEntries { count, limit } => {
+                write!(f, "Too many entries: {}, limit is {}", count, limit)
+            }
+            NetworkSerializationError::AuxPowMismatch => {
+                write!(
+                    f,
+                    "Auxpow present without the version bit or the other way around"
+                )
+            }
+            NetworkSerializationError::Custom(message) => write!(f, "{}", message),
         }
     }
 }
 
 impl std::error::Error for NetworkSerializationError {}
 
+#[derive(Debug)]
+pub enum VersionBuildError {
+    FailedCreatingUnixTimestamp(SystemTimeError),
+    ProtocolVersionTooLow(u32),
+    UserAgentTooLong(usize),
+    InvalidUserAgent(UserAgentError),
+}
+
+impl std::fmt::Display for VersionBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionBuildError::FailedCreatingUnixTimestamp(e) => {
+                write!(f, "Failed creating unix timestamp: {}", e)
+            }
+            VersionBuildError::ProtocolVersionTooLow(version) => {
+                write!(f, "Protocol version too low: {}", version)
+            }
+            VersionBuildError::UserAgentTooLong(length) => {
+                write!(f, "User agent too long: {} bytes", length)
+            }
+            VersionBuildError::InvalidUserAgent(e) => write!(f, "Invalid user agent: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VersionBuildError {}
+
+impl From<SystemTimeError> for VersionBuildError {
+    fn from(value: SystemTimeError) -> Self {
+        VersionBuildError::FailedCreatingUnixTimestamp(value)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UserAgentError {
+    TooLong(usize),
+    NonPrintable { offset: usize, byte: u8 },
+    MissingSlashes,
+    EmptyName,
+    UnbalancedComment,
+}
+
+impl std::fmt::Display for UserAgentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserAgentError::TooLong(length) => write!(f, "User agent too long: {} bytes", length),
+            UserAgentError::NonPrintable { offset, byte } => write!(
+                f,
+                "User agent contains non-printable byte 0x{:02x} at offset {}",
+                byte, offset
+            ),
+            UserAgentError::MissingSlashes => write!(f, "User agent is not wrapped in slashes"),
+            UserAgentError::EmptyName => write!(f, "User agent component has an empty name"),
+            UserAgentError::UnbalancedComment => {
+                write!(f, "User agent comment parentheses are unbalanced")
+            }
+        }
+    }
+}
+
+impl std::error::Error for UserAgentError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProofOfWorkError {
+    HashAboveTarget { hash: Hash256, bits: u32 },
+    NegativeTarget(u32),
+    TargetOverflow(u32),
+    ZeroTarget(u32),
+}
+
+impl std::fmt::Display for ProofOfWorkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProofOfWorkError::HashAboveTarget { hash, bits } => write!(
+                f,
+                "Proof of work hash {} is above target {:#010x}",
+                hash, bits
+            ),
+            ProofOfWorkError::NegativeTarget(bits) => {
+                write!(f, "Compact target {:#010x} is negative", bits)
+            }
+            ProofOfWorkError::TargetOverflow(bits) => {
+                write!(f, "Compact target {:#010x} overflows 256 bits", bits)
+            }
+            ProofOfWorkError::ZeroTarget(bits) => {
+                write!(f, "Compact target {:#010x} is zero", bits)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProofOfWorkError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuxPowError {
+    BranchSizeMismatch { size: u32, expected: u32 },
+    ChainBranchTooLong(usize),
+    ChainRootNotAfterHeader,
+    ChainRootTooLate(usize),
+    CoinbaseNotFirst(i32),
+    CoinbaseNotInParent,
+    MalformedCoinbase,
+    MissingChainRoot,
+    MissingSizeAndNonce,
+    MultipleMergedMiningHeaders,
+    ParentHashMismatch { expected: Hash256, actual: Hash256 },
+    ParentHasOurChainId(i32),
+    WrongChainIndex { index: i32, expected: i32 },
+}
+
+impl std::fmt::Display for AuxPowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuxPowError::BranchSizeMismatch { size, expected } => write!(
+                f,
+                "Chain merkle tree size {} in the coinbase, branch says {}",
+                size, expected
+            ),
+            AuxPowError::ChainBranchTooLong(length) => {
+                write!(f, "Chain merkle branch of {} hashes is too long", length)
+            }
+            AuxPowError::ChainRootNotAfterHeader => write!(
+                f,
+                "Chain merkle root does not follow the merged mining header"
+            ),
+            AuxPowError::ChainRootTooLate(offset) => write!(
+                f,
+                "Chain merkle root at offset {} is not in the first 20 bytes of the coinbase",
+                offset
+            ),
+            AuxPowError::CoinbaseNotFirst(index) => {
+                write!(f, "Coinbase index {} is not the first transaction", index)
+            }
+            AuxPowError::CoinbaseNotInParent => {
+                write!(f, "Coinbase is not in the parent block merkle tree")
+            }
+            AuxPowError::MalformedCoinbase => write!(f, "Coinbase has no input script"),
+            AuxPowError::MissingChainRoot => {
+                write!(f, "Chain merkle root is missing from the coinbase")
+            }
+            AuxPowError::MissingSizeAndNonce => write!(
+                f,
+                "Chain merkle tree size and nonce are missing from the coinbase"
+            ),
+            AuxPowError::MultipleMergedMiningHeaders => {
+                write!(f, "Coinbase has more than one merged mining header")
+            }
+            AuxPowError::ParentHashMismatch { expected, actual } => write!(
+                f,
+                "Parent block hash {} does not match its header {}",
+                actual, expected
+            ),
+            AuxPowError::ParentHasOurChainId(chain_id) => {
+                write!(f, "Parent block has our chain id {:#x}", chain_id)
+            }
+            AuxPowError::WrongChainIndex { index, expected } => write!(
+                f,
+                "Chain merkle index {} does not match the expected {}",
+                index, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AuxPowError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderChainError {
+    AuxPowMismatch(Hash256),
+    DoesNotConnect {
+        tip: Hash256,
+        prev_block: Hash256,
+    },
+    InsufficientChainWork {
+        work: ChainWork,
+        minimum: ChainWork,
+    },
+    InvalidAuxPow(Hash256, AuxPowError),
+    InvalidProofOfWork(Hash256, ProofOfWorkError),
+    TargetAboveLimit {
+        bits: u32,
+        limit_bits: u32,
+    },
+    WrongChainId {
+        hash: Hash256,
+        chain_id: i32,
+        expected: i32,
+    },
+}
+
+impl std::fmt::Display for HeaderChainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderChainError::AuxPowMismatch(hash) => {
+                write!(f, "Header {} auxpow does not match its version bits", hash)
+            }
+            HeaderChainError::DoesNotConnect { tip, prev_block } => write!(
+                f,
+                "Header does not connect, tip: {}, previous block: {}",
+                tip, prev_block
+            ),
+            HeaderChainError::InsufficientChainWork { work, minimum } => write!(
+                f,
+                "Chain work {} is below the network minimum {}",
+                work, minimum
+            ),
+            HeaderChainError::InvalidAuxPow(hash, e) => {
+                write!(f, "Header {} has invalid auxpow: {}", hash, e)
+            }
+            HeaderChainError::InvalidProofOfWork(hash, e) => {
+                write!(f, "Header {} has invalid proof of work: {}", hash, e)
+            }
+            HeaderChainError::TargetAboveLimit { bits, limit_bits } => write!(
+                f,
+                "Target {:#010x} is easier than the network limit {:#010x}",
+                bits, limit_bits
+            ),
+            HeaderChainError::WrongChainId {
+                hash,
+                chain_id,
+                expected,
+            } => write!(
+                f,
+                "Header {} has chain id {:#06x}, expected {:#06x}",
+                hash, chain_id, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderChainError {}
+
 #[derive(Debug, PartialEq)]
 pub struct IntegerParsingFailure;
 
@@ -113,52 +410,117 @@ impl From<IntegerParsingFailure> for NetworkSerializationError {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeoutPhase {
+    Connect,
+    Read,
+    Write,
+    Handshake,
+    HeaderSync,
+}
+
+impl std::fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeoutPhase::Connect => write!(f, "connect"),
+            TimeoutPhase::Read => write!(f, "read"),
+            TimeoutPhase::Write => write!(f, "write"),
+            TimeoutPhase::Handshake => write!(f, "handshake"),
+            TimeoutPhase::HeaderSync => write!(f, "header sync"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum NodeConnectionAgentError {
-    FailedCreatingUnixTimestamp(SystemTimeError),
+    Cancelled,
+    ChecksumMismatch { expected: [u8; 4], actual: [u8; 4] },
+    ConnectionClosed,
+    HandshakeIncomplete,
+    HandshakeRejected(Reject),
+    HeaderChainFailure(HeaderChainError),
     HeaderBuildFailure(HeaderBuildError),
-    IncorrectNumberOfBytesReceived,
-    IncorrectNumberOfBytesSent,
-    IncorrectResponse,
     IntegerParsingFailure,
     IoError(std::io::Error),
+    MessageTooLarge(usize),
     NetworkSerializationFailure(NetworkSerializationError),
+    NoCandidatePeers,
+    PingTimeout(u32),
+    Timeout { phase: TimeoutPhase },
     UnexpectedCommand(String, String),
+    UnexpectedNetworkMagic([u8; 4], [u8; 4]),
+    VersionBuildFailure(VersionBuildError),
 }
 
 impl std::fmt::Display for NodeConnectionAgentError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            NodeConnectionAgentError::FailedCreatingUnixTimestamp(e) => {
-                write!(f, "Failed creating unix timestamp: {}", e)
+            NodeConnectionAgentError::Cancelled => write!(f, "Connection cancelled"),
+            NodeConnectionAgentError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch, expected: {:02x?}, actual: {:02x?}",
+                expected, actual
+            ),
+            NodeConnectionAgentError::ConnectionClosed => write!(f, "Connection closed by peer"),
+            NodeConnectionAgentError::HandshakeIncomplete => write!(f, "Handshake incomplete"),
+            NodeConnectionAgentError::HandshakeRejected(reject) => write!(
+                f,
+                "Handshake rejected by peer, code: {:#04x}, reason: '{}'",
+                reject.code,
+                sanitize_string(&reject.reason)
+            ),
+            NodeConnectionAgentError::HeaderChainFailure(e) => {
+                write!(f, "Header chain failure: {}", e)
             }
             NodeConnectionAgentError::HeaderBuildFailure(e) => {
                 write!(f, "Header build error: {}", e)
             }
-            NodeConnectionAgentError::IncorrectNumberOfBytesReceived => {
-                write!(f, "Incorrect number of bytes received")
-            }
-            NodeConnectionAgentError::IncorrectNumberOfBytesSent => {
-                write!(f, "Incorrect number of bytes sent")
-            }
-            NodeConnectionAgentError::IncorrectResponse => write!(f, "Incorrect response"),
             NodeConnectionAgentError::IntegerParsingFailure => write!(f, "Integer parsing failure"),
             NodeConnectionAgentError::IoError(e) => write!(f, "I/O error: {}", e),
+            NodeConnectionAgentError::MessageTooLarge(size) => {
+                write!(f, "Message too large: {} bytes", size)
+            }
             NodeConnectionAgentError::NetworkSerializationFailure(e) => {
                 write!(f, "Network serialization failure: {}", e)
             }
+            NodeConnectionAgentError::NoCandidatePeers => {
+                write!(f, "No candidate peers, DNS seeds returned no addresses")
+            }
+            NodeConnectionAgentError::PingTimeout(missed_pongs) => {
+                write!(f, "Peer did not answer {} pings in a row", missed_pongs)
+            }
+            NodeConnectionAgentError::Timeout { phase } => write!(f, "Timed out during {}", phase),
             NodeConnectionAgentError::UnexpectedCommand(expected, actual) => write!(
                 f,
                 "Unexpected command, expected: '{}', actual: '{}",
                 expected, actual
             ),
+            NodeConnectionAgentError::UnexpectedNetworkMagic(expected, actual) => write!(
+                f,
+                "Unexpected network magic, expected: {:02x?}, actual: {:02x?}",
+                expected, actual
+            ),
+            NodeConnectionAgentError::VersionBuildFailure(e) => {
+                write!(f, "Version build failure: {}", e)
+            }
         }
     }
 }
 
-impl From<SystemTimeError> for NodeConnectionAgentError {
-    fn from(value: SystemTimeError) -> Self {
-        NodeConnectionAgentError::FailedCreatingUnixTimestamp(value)
+impl NodeConnectionAgentError {
+    pub fn from_io_error(error: std::io::Error, phase: TimeoutPhase) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                NodeConnectionAgentError::Timeout { phase }
+            }
+            _ => NodeConnectionAgentError::IoError(error),
+        }
+    }
+}
+
+impl From<VersionBuildError> for NodeConnectionAgentError {
+    fn from(value: VersionBuildError) -> Self {
+        NodeConnectionAgentError::VersionBuildFailure(value)
     }
 }
 
@@ -186,4 +548,10 @@ impl From<HeaderBuildError> for NodeConnectionAgentError {
     }
 }
 
+impl From<HeaderChainError> for NodeConnectionAgentError {
+    fn from(value: HeaderChainError) -> Self {
+        NodeConnectionAgentError::HeaderChainFailure(value)
+    }
+}
+
 impl std::error::Error for NodeConnectionAgentError {}
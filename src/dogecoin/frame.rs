@@ -0,0 +1,179 @@
+use super::*;
+use errors::*;
+use header::{Header, HEADER_SIZE};
+use messages::Message;
+
+// Same limit as MAX_PROTOCOL_MESSAGE_LENGTH in Dogecoin Core
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1000 * 1000;
+
+pub const RECEIVE_CHUNK_SIZE: usize = 4096;
+
+// Takes one complete frame from the front of the buffer, Ok(None) means more bytes are needed
+pub fn decode_frame(
+    buffer: &mut Vec<u8>,
+    network: &NetworkParams,
+    max_message_size: usize,
+    verify_checksum: bool,
+) -> Result<Option<Message>, NodeConnectionAgentError> {
+    if buffer.len() < HEADER_SIZE {
+        return Ok(None);
+    }
+
+    let header = Header::from_network_bytes(&buffer[0..HEADER_SIZE])?;
+    if header.magic != network.magic {
+        return Err(NodeConnectionAgentError::UnexpectedNetworkMagic(
+            network.magic,
+            header.magic,
+        ));
+    }
+    if header.message_size > max_message_size {
+        return Err(NodeConnectionAgentError::MessageTooLarge(
+            header.message_size,
+        ));
+    }
+
+    let frame_size = HEADER_SIZE + header.message_size;
+    if buffer.len() < frame_size {
+        return Ok(None);
+    }
+
+    let payload: Vec<u8> = buffer.drain(0..frame_size).skip(HEADER_SIZE).collect();
+
+    if verify_checksum {
+        let checksum = calculate_message_hash(&payload);
+        if checksum != header.hash {
+            return Err(NodeConnectionAgentError::ChecksumMismatch {
+                expected: header.hash,
+                actual: checksum,
+            });
+        }
+    }
+
+    Ok(Some(Message::from_payload(
+        header.command.as_str(),
+        &payload,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_frame_needs_more_bytes() {
+        let frame = Message::Verack
+            .to_network_bytes(&NetworkParams::TEST)
+            .unwrap();
+
+        let mut buffer = frame[0..10].to_vec();
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                true
+            ),
+            Ok(None)
+        ));
+        assert_eq!(10, buffer.len());
+
+        let mut buffer = frame.clone();
+        buffer.extend_from_slice(&frame[0..5]);
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                true
+            ),
+            Ok(Some(Message::Verack))
+        ));
+        assert_eq!(&frame[0..5], buffer.as_slice());
+    }
+
+    #[test]
+    fn test_decode_frame_waits_for_payload() {
+        let frame = Message::Unknown {
+            command: "alert".to_string(),
+            payload: vec![1, 2, 3],
+        }
+        .to_network_bytes(&NetworkParams::TEST)
+        .unwrap();
+
+        let mut buffer = frame[0..HEADER_SIZE + 1].to_vec();
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                true
+            ),
+            Ok(None)
+        ));
+
+        buffer.extend_from_slice(&frame[HEADER_SIZE + 1..]);
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                true
+            ),
+            Ok(Some(Message::Unknown { .. }))
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_frame_unexpected_network() {
+        let mut buffer = Message::Verack
+            .to_network_bytes(&NetworkParams::MAIN)
+            .unwrap();
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                true
+            ),
+            Err(NodeConnectionAgentError::UnexpectedNetworkMagic(
+                [0xFC, 0xC1, 0xB7, 0xDC],
+                [0xC0, 0xC0, 0xC0, 0xC0]
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_decode_frame_checksum() {
+        let frame = Message::Verack
+            .to_network_bytes(&NetworkParams::TEST)
+            .unwrap();
+        let mut corrupted = frame.clone();
+        corrupted[HEADER_SIZE - 1] ^= 0xFF;
+
+        let mut buffer = corrupted.clone();
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                true
+            ),
+            Err(NodeConnectionAgentError::ChecksumMismatch {
+                expected: [0x5D, 0xF6, 0xE0, 0x1D],
+                actual: [0x5D, 0xF6, 0xE0, 0xE2],
+            })
+        ));
+
+        let mut buffer = corrupted;
+        assert!(matches!(
+            decode_frame(
+                &mut buffer,
+                &NetworkParams::TEST,
+                DEFAULT_MAX_MESSAGE_SIZE,
+                false
+            ),
+            Ok(Some(Message::Verack))
+        ));
+    }
+}
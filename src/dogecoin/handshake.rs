@@ -0,0 +1,285 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::*;
+use errors::*;
+use messages::{version::VersionMessageData, Message};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandshakeState {
+    AwaitingVersionAndVerack,
+    AwaitingVersion,
+    AwaitingVerack,
+    Complete,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandshakeRole {
+    Initiator,
+    Responder,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandshakeAction {
+    None,
+    SendPong(u64),
+    SendVerack,
+    SendVersionAndVerack,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HandshakeOutcome {
+    pub peer_version: VersionMessageData,
+    pub protocol_version: u32,
+    pub version_received_after: Duration,
+    pub elapsed: Duration,
+}
+
+pub struct Handshake {
+    buffered_messages: VecDeque<Message>,
+    local_protocol_version: u32,
+    peer_version: Option<VersionMessageData>,
+    role: HandshakeRole,
+    started_at: Instant,
+    state: HandshakeState,
+    version_received_after: Duration,
+}
+
+impl Handshake {
+    pub fn new(local_protocol_version: u32) -> Self {
+        Self::with_role(HandshakeRole::Initiator, local_protocol_version)
+    }
+
+    // Side of an inbound connection, our version is sent only after peer's one arrives
+    pub fn new_responder(local_protocol_version: u32) -> Self {
+        Self::with_role(HandshakeRole::Responder, local_protocol_version)
+    }
+
+    fn with_role(role: HandshakeRole, local_protocol_version: u32) -> Self {
+        Self {
+            buffered_messages: VecDeque::new(),
+            local_protocol_version,
+            peer_version: None,
+            role,
+            started_at: Instant::now(),
+            state: HandshakeState::AwaitingVersionAndVerack,
+            version_received_after: Duration::ZERO,
+        }
+    }
+
+    pub fn state(&self) -> &HandshakeState {
+        &self.state
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == HandshakeState::Complete
+    }
+
+    pub fn on_message(
+        &mut self,
+        message: Message,
+    ) -> Result<HandshakeAction, NodeConnectionAgentError> {
+        match (&self.state, message) {
+            (HandshakeState::AwaitingVersionAndVerack, Message::Version(data)) => {
+                self.accept_version(data, HandshakeState::AwaitingVerack)
+            }
+            (HandshakeState::AwaitingVersion, Message::Version(data)) => {
+                self.accept_version(data, HandshakeState::Complete)
+            }
+            (HandshakeState::AwaitingVersionAndVerack, Message::Verack)
+                if self.role == HandshakeRole::Initiator =>
+            {
+                self.state = HandshakeState::AwaitingVersion;
+                Ok(HandshakeAction::None)
+            }
+            (HandshakeState::AwaitingVerack, Message::Verack) => {
+                self.state = HandshakeState::Complete;
+                Ok(HandshakeAction::None)
+            }
+            (_, Message::Reject(reject)) => {
+                Err(NodeConnectionAgentError::HandshakeRejected(reject))
+            }
+            (state, message @ (Message::Version(_) | Message::Verack)) => {
+                Err(NodeConnectionAgentError::UnexpectedCommand(
+                    Self::expected_command(state).to_string(),
+                    message.command().to_string(),
+                ))
+            }
+            (_, Message::Ping(ping)) => Ok(HandshakeAction::SendPong(ping.nonce)),
+            (_, message) => {
+                // Dogecoin Core ignores everything sent before version, later messages
+                // (sendheaders, sendcmpct, feefilter...) are kept for the caller
+                if self.peer_version.is_some() {
+                    self.buffered_messages.push_back(message);
+                }
+                Ok(HandshakeAction::None)
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(HandshakeOutcome, VecDeque<Message>), NodeConnectionAgentError> {
+        let peer_version = match (self.state, self.peer_version) {
+            (HandshakeState::Complete, Some(peer_version)) => peer_version,
+            _ => return Err(NodeConnectionAgentError::HandshakeIncomplete),
+        };
+
+        let outcome = HandshakeOutcome {
+            protocol_version: self
+                .local_protocol_version
+                .min(peer_version.protocol_version),
+            peer_version,
+            version_received_after: self.version_received_after,
+            elapsed: self.started_at.elapsed(),
+        };
+
+        Ok((outcome, self.buffered_messages))
+    }
+
+    fn accept_version(
+        &mut self,
+        data: VersionMessageData,
+        next_state: HandshakeState,
+    ) -> Result<HandshakeAction, NodeConnectionAgentError> {
+        self.peer_version = Some(data);
+        self.version_received_after = self.started_at.elapsed();
+        self.state = next_state;
+
+        match self.role {
+            HandshakeRole::Initiator => Ok(HandshakeAction::SendVerack),
+            HandshakeRole::Responder => Ok(HandshakeAction::SendVersionAndVerack),
+        }
+    }
+
+    fn expected_command(state: &HandshakeState) -> &'static str {
+        match state {
+            HandshakeState::AwaitingVersionAndVerack | HandshakeState::AwaitingVersion => "version",
+            HandshakeState::AwaitingVerack => "verack",
+            HandshakeState::Complete => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::{ping::Ping, reject::Reject};
+
+    fn peer_version() -> VersionMessageData {
+        VersionMessageData::from_network_bytes(&crate::dogecoin::tests::VERSION_MESSAGE).unwrap()
+    }
+
+    #[test]
+    fn test_version_then_verack() {
+        let mut handshake = Handshake::new(70016);
+        assert_eq!(
+            HandshakeAction::SendVerack,
+            handshake
+                .on_message(Message::Version(peer_version()))
+                .unwrap()
+        );
+        assert_eq!(&HandshakeState::AwaitingVerack, handshake.state());
+        assert_eq!(
+            HandshakeAction::None,
+            handshake.on_message(Message::Verack).unwrap()
+        );
+        assert!(handshake.is_complete());
+
+        let (outcome, buffered) = handshake.finish().unwrap();
+        assert_eq!(peer_version(), outcome.peer_version);
+        assert_eq!(70015, outcome.protocol_version);
+        assert!(outcome.version_received_after <= outcome.elapsed);
+        assert!(buffered.is_empty());
+    }
+
+    #[test]
+    fn test_verack_then_version_with_extra_messages() {
+        let mut handshake = Handshake::new(70015);
+        handshake.on_message(Message::SendHeaders).unwrap();
+        handshake.on_message(Message::Verack).unwrap();
+        assert_eq!(&HandshakeState::AwaitingVersion, handshake.state());
+        assert_eq!(
+            HandshakeAction::SendPong(5),
+            handshake
+                .on_message(Message::Ping(Ping { nonce: 5 }))
+                .unwrap()
+        );
+        assert_eq!(
+            HandshakeAction::SendVerack,
+            handshake
+                .on_message(Message::Version(peer_version()))
+                .unwrap()
+        );
+        assert!(handshake.is_complete());
+
+        let mut handshake = Handshake::new(70015);
+        handshake
+            .on_message(Message::Version(peer_version()))
+            .unwrap();
+        handshake.on_message(Message::SendHeaders).unwrap();
+        handshake.on_message(Message::Verack).unwrap();
+        let (_, buffered) = handshake.finish().unwrap();
+        assert_eq!(vec![Message::SendHeaders], Vec::from(buffered));
+    }
+
+    #[test]
+    fn test_duplicate_messages() {
+        let mut handshake = Handshake::new(70015);
+        handshake
+            .on_message(Message::Version(peer_version()))
+            .unwrap();
+        assert!(matches!(
+            handshake.on_message(Message::Version(peer_version())),
+            Err(NodeConnectionAgentError::UnexpectedCommand(expected, actual))
+                if expected == "verack" && actual == "version"
+        ));
+
+        let mut handshake = Handshake::new(70015);
+        handshake.on_message(Message::Verack).unwrap();
+        assert!(handshake.on_message(Message::Verack).is_err());
+    }
+
+    #[test]
+    fn test_responder() {
+        let mut handshake = Handshake::new_responder(70015);
+        assert!(matches!(
+            handshake.on_message(Message::Verack),
+            Err(NodeConnectionAgentError::UnexpectedCommand(expected, actual))
+                if expected == "version" && actual == "verack"
+        ));
+
+        let mut handshake = Handshake::new_responder(70015);
+        assert_eq!(
+            HandshakeAction::SendVersionAndVerack,
+            handshake
+                .on_message(Message::Version(peer_version()))
+                .unwrap()
+        );
+        assert_eq!(
+            HandshakeAction::None,
+            handshake.on_message(Message::Verack).unwrap()
+        );
+        assert!(handshake.is_complete());
+    }
+
+    #[test]
+    fn test_rejected() {
+        let mut handshake = Handshake::new(70015);
+        let reject = Reject {
+            message: b"version".to_vec(),
+            code: 0x11,
+            reason: b"Version must be 70003 or greater".to_vec(),
+            data: None,
+        };
+        assert!(matches!(
+            handshake.on_message(Message::Reject(reject)),
+            Err(NodeConnectionAgentError::HandshakeRejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_finish_incomplete() {
+        let mut handshake = Handshake::new(70015);
+        handshake.on_message(Message::Verack).unwrap();
+        assert!(handshake.finish().is_err());
+    }
+}
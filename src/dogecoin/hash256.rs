@@ -0,0 +1,98 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use serializer::{Decode, Encode, Reader};
+
+// Tx and block hashes, stored in wire order but displayed reversed like Core does
+
+pub const HASH256_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Hash256([u8; HASH256_SIZE]);
+
+impl Hash256 {
+    pub const ZERO: Hash256 = Hash256([0; HASH256_SIZE]);
+
+    pub const fn from_bytes(bytes: [u8; HASH256_SIZE]) -> Self {
+        Hash256(bytes)
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; HASH256_SIZE] {
+        &self.0
+    }
+
+    pub const fn from_hex(hex: &str) -> Self {
+        Hash256(network_params::hash_from_hex(hex))
+    }
+}
+
+impl From<[u8; HASH256_SIZE]> for Hash256 {
+    fn from(value: [u8; HASH256_SIZE]) -> Self {
+        Hash256(value)
+    }
+}
+
+impl From<Hash256> for [u8; HASH256_SIZE] {
+    fn from(value: Hash256) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter().rev() {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash256({})", self)
+    }
+}
+
+impl Decode for Hash256 {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        Ok(Hash256(reader.read_array()?))
+    }
+}
+
+impl Encode for Hash256 {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        self.0.encode(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_is_reversed() {
+        let mut bytes = [0; HASH256_SIZE];
+        bytes[0] = 0x01;
+        bytes[31] = 0xAB;
+        let hash = Hash256::from_bytes(bytes);
+        assert_eq!(
+            "ab00000000000000000000000000000000000000000000000000000000000001",
+            hash.to_string()
+        );
+        assert_eq!(
+            "Hash256(ab00000000000000000000000000000000000000000000000000000000000001)",
+            format!("{:?}", hash)
+        );
+
+        let genesis = "bb0a78264637406b6360aad926284d544d7049f45189db5664f3c4d07350559e";
+        assert_eq!(genesis, Hash256::from_hex(genesis).to_string());
+        assert_eq!(
+            Hash256::from(NetworkParams::TEST.genesis_hash),
+            Hash256::from_hex(genesis)
+        );
+    }
+}
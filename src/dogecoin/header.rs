@@ -1,11 +1,15 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 use super::*;
 use errors::*;
-use serializer::{slice_to_string, slice_to_u32};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Header {
-    pub network_type: NetworkType,
-    pub command: String,
+    pub magic: [u8; 4],
+    pub command: Command,
+    #[serde(with = "message_size")]
     pub message_size: usize,
     pub hash: [u8; 4],
 }
@@ -16,66 +20,156 @@ impl Header {
             return Err(HeaderBuildError::TooShort);
         }
 
-        let network_type = NetworkType::from_network_bytes(&bytes[0..4]);
-        if let Err(e) = network_type {
-            return match e {
-                NetworkSerializationError::BufferTooShort => Err(HeaderBuildError::TooShort),
-                NetworkSerializationError::UnknownBytes => {
-                    Err(HeaderBuildError::UnknownNetworkType)
-                }
-                NetworkSerializationError::HeaderParseError(e) => Err(e),
-                NetworkSerializationError::StringParseError => {
-                    Err(HeaderBuildError::CommandTooLong)
-                }
-            };
+        // Checked up front so a bad command keeps its dedicated error
+        let command_offset = NETWORK_TYPE_HEADER_SIZE;
+        Command::from_bytes(
+            bytes[command_offset..command_offset + COMMAND_SIZE]
+                .try_into()
+                .unwrap(),
+        )?;
+
+        // Magic is not checked here, only the agent knows which network it expects
+        Ok(serde_wire::from_bytes(bytes)?)
+    }
+
+    pub fn to_network_bytes(&self, message: &[u8]) -> Result<Vec<u8>, HeaderBuildError> {
+        if u32::try_from(message.len()).is_err() {
+            return Err(HeaderBuildError::MessageTooLong(message.len()));
         }
 
-        let command = slice_to_string(&bytes[4..16]);
-        if command.is_empty() {
+        let header = Header {
+            magic: self.magic,
+            command: self.command,
+            message_size: message.len(),
+            hash: super::calculate_message_hash(message),
+        };
+
+        Ok(serde_wire::to_bytes(&header)?)
+    }
+}
+
+// NUL padded printable ASCII, same rules as IsCommandValid in Dogecoin Core
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct Command([u8; COMMAND_SIZE]);
+
+impl Command {
+    pub fn new(value: &str) -> Result<Self, HeaderBuildError> {
+        if value.is_empty() {
             return Err(HeaderBuildError::CommandIsEmpty);
         }
+        if value.len() > COMMAND_SIZE {
+            return Err(HeaderBuildError::CommandTooLong);
+        }
+        if let Some(offset) = value.bytes().position(|byte| !is_printable(byte)) {
+            return Err(HeaderBuildError::CommandNotPrintable {
+                offset,
+                byte: value.as_bytes()[offset],
+            });
+        }
 
-        let message_size =
-            slice_to_u32(&bytes[16..20]).ok_or(HeaderBuildError::MessageSizeParseFailure)?;
-        let message_size = message_size as usize;
+        let mut bytes = [0; COMMAND_SIZE];
+        bytes[..value.len()].copy_from_slice(value.as_bytes());
 
-        Ok(Self {
-            network_type: network_type.unwrap(),
-            command,
-            message_size,
-            hash: [bytes[20], bytes[21], bytes[22], bytes[23]],
-        })
+        Ok(Command(bytes))
     }
 
-    pub fn to_network_bytes(&self, message: &[u8]) -> Result<Vec<u8>, HeaderBuildError> {
-        if self.command.len() > COMMAND_SIZE {
-            return Err(HeaderBuildError::CommandTooLong);
+    // For literals, an invalid one fails to compile when used in a const
+    pub const fn from_static(value: &'static str) -> Self {
+        let value = value.as_bytes();
+        assert!(!value.is_empty() && value.len() <= COMMAND_SIZE);
+
+        let mut bytes = [0; COMMAND_SIZE];
+        let mut index = 0;
+        while index < value.len() {
+            assert!(is_printable(value[index]));
+            bytes[index] = value[index];
+            index += 1;
         }
 
-        let length: Option<u32> = message.len().try_into().ok();
-        if length.is_none() {
-            return Err(HeaderBuildError::MessageTooLong(message.len()));
+        Command(bytes)
+    }
+
+    // All NUL passes IsCommandValid too, peers may send it and we just won't know the message
+    pub fn from_bytes(bytes: [u8; COMMAND_SIZE]) -> Result<Self, HeaderBuildError> {
+        let mut terminated = false;
+        for (offset, byte) in bytes.iter().enumerate() {
+            match *byte {
+                0 => terminated = true,
+                _ if terminated => {
+                    return Err(HeaderBuildError::CommandDataAfterPadding { offset })
+                }
+                byte if !is_printable(byte) => {
+                    return Err(HeaderBuildError::CommandNotPrintable { offset, byte })
+                }
+                _ => {}
+            }
         }
-        let length = length.unwrap();
 
-        let mut buffer = Vec::new();
-        buffer.reserve(HEADER_SIZE);
+        Ok(Command(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; COMMAND_SIZE] {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        let length = self
+            .0
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(COMMAND_SIZE);
+
+        // Only printable ASCII gets past the constructors
+        std::str::from_utf8(&self.0[..length]).unwrap_or_default()
+    }
+}
+
+const fn is_printable(byte: u8) -> bool {
+    byte >= b' ' && byte <= b'~'
+}
 
-        buffer.extend_from_slice(self.network_type.to_network_bytes()?.as_slice());
-        buffer.extend_from_slice(self.command.as_bytes());
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Command({:?})", self.as_str())
+    }
+}
 
-        let padding_size = COMMAND_SIZE - self.command.len();
-        buffer.resize(buffer.len() + padding_size, 0);
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
-        buffer.extend_from_slice(length.to_le_bytes().as_slice());
-        buffer.extend_from_slice(&super::calculate_message_hash(message));
+impl Serialize for Command {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
 
-        Ok(buffer)
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Command::from_bytes(<[u8; COMMAND_SIZE]>::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// usize in memory, u32 on the wire
+mod message_size {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &usize, serializer: S) -> Result<S::Ok, S::Error> {
+        u32::try_from(*value)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+        Ok(u32::deserialize(deserializer)? as usize)
     }
 }
 
 const NETWORK_TYPE_HEADER_SIZE: usize = 4;
-const COMMAND_SIZE: usize = 12;
+pub const COMMAND_SIZE: usize = 12;
 const MESSAGE_SIZE_SIZE: usize = 4;
 const HASH_SIZE: usize = 4;
 
@@ -84,52 +178,83 @@ pub const HEADER_SIZE: usize =
 
 #[cfg(test)]
 mod tests {
-    use super::super::NetworkSerializable;
     use super::*;
 
     const VERACK_HEADER: [u8; 24] = [
-        0xFC, 0xC1, 0xB7, 0xDC, 'v' as u8, 'e' as u8, 'r' as u8, 'a' as u8, 'c' as u8, 'k' as u8,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5D, 0xF6, 0xE0, 0xE2,
+        0xFC, 0xC1, 0xB7, 0xDC, b'v', b'e', b'r', b'a', b'c', b'k', 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x5D, 0xF6, 0xE0, 0xE2,
     ];
     const VERSION_HEADER: [u8; 24] = [
-        0xFC, 0xC1, 0xB7, 0xDC, 'v' as u8, 'e' as u8, 'r' as u8, 's' as u8, 'i' as u8, 'o' as u8,
-        'n' as u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x69, 0x00, 0x00, 0x00, 0xA2, 0xBB, 0x58, 0x1C,
+        0xFC, 0xC1, 0xB7, 0xDC, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x69, 0x00, 0x00, 0x00, 0xA2, 0xBB, 0x58, 0x1C,
     ];
 
     #[test]
-    fn test_build_network_type_header() {
-        let bytes = NetworkType::Main.to_network_bytes();
-        assert!(bytes.is_ok());
-        assert_eq!(&[0xC0, 0xC0, 0xC0, 0xC0], bytes.unwrap().as_slice());
-
-        let bytes = NetworkType::Test.to_network_bytes();
-        assert!(bytes.is_ok());
-        assert_eq!(&[0xFC, 0xC1, 0xB7, 0xDC], bytes.unwrap().as_slice());
+    fn test_command_validation() {
+        assert_eq!(
+            Err(HeaderBuildError::CommandTooLong),
+            Command::new("verylongcommandname")
+        );
+        assert_eq!(Err(HeaderBuildError::CommandIsEmpty), Command::new(""));
+        assert_eq!(
+            Err(HeaderBuildError::CommandNotPrintable { offset: 3, byte: 0 }),
+            Command::new("ver\0ack")
+        );
+        assert_eq!("verack", Command::new("verack").unwrap().as_str());
 
-        let bytes = NetworkType::RegressionTest.to_network_bytes();
-        assert!(bytes.is_ok());
-        assert_eq!(&[0xFA, 0xBF, 0xB5, 0xDA], bytes.unwrap().as_slice());
+        let mut bytes = *b"ver\0ack\0\0\0\0\0";
+        assert_eq!(
+            Err(HeaderBuildError::CommandDataAfterPadding { offset: 4 }),
+            Command::from_bytes(bytes)
+        );
+        bytes[3] = 0x07;
+        assert_eq!(
+            Err(HeaderBuildError::CommandNotPrintable {
+                offset: 3,
+                byte: 0x07
+            }),
+            Command::from_bytes(bytes)
+        );
+        assert_eq!("", Command::from_bytes([0; COMMAND_SIZE]).unwrap().as_str());
+        assert_eq!(
+            Ok(Command::from_static("sendheaders")),
+            Command::from_bytes(*b"sendheaders\0")
+        );
     }
 
     #[test]
-    fn test_build_header_command_too_long() {
-        let message = Vec::new();
-        let result = Header {
-            network_type: NetworkType::Test,
-            command: "verylongcommandname".to_string(),
-            message_size: 0,
-            hash: [0; 4],
-        }
-        .to_network_bytes(&message);
-        assert_eq!(Err(HeaderBuildError::CommandTooLong), result);
+    fn test_parse_header_invalid_command() {
+        let mut bytes = VERACK_HEADER;
+        bytes[14] = b'x';
+        assert_eq!(
+            Err(HeaderBuildError::CommandDataAfterPadding { offset: 10 }),
+            Header::from_network_bytes(&bytes)
+        );
+
+        let mut bytes = VERACK_HEADER;
+        bytes[4] = 0xFF;
+        assert_eq!(
+            Err(HeaderBuildError::CommandNotPrintable {
+                offset: 0,
+                byte: 0xFF
+            }),
+            Header::from_network_bytes(&bytes)
+        );
+
+        let mut bytes = VERACK_HEADER;
+        bytes[4..16].fill(0);
+        assert_eq!(
+            "",
+            Header::from_network_bytes(&bytes).unwrap().command.as_str()
+        );
     }
 
     #[test]
     fn test_build_header_verack() {
         let message = Vec::new();
         let bytes = Header {
-            network_type: NetworkType::Test,
-            command: "verack".to_string(),
+            magic: NetworkParams::TEST.magic,
+            command: Command::from_static("verack"),
             message_size: 0,
             hash: [0; 4],
         }
@@ -140,8 +265,8 @@ mod tests {
     #[test]
     fn test_build_header_version() {
         let bytes = Header {
-            network_type: NetworkType::Test,
-            command: "version".to_string(),
+            magic: NetworkParams::TEST.magic,
+            command: Command::from_static("version"),
             message_size: crate::dogecoin::tests::VERSION_MESSAGE.len(),
             hash: [0; 4],
         }
@@ -155,8 +280,8 @@ mod tests {
         assert!(header.is_ok());
 
         let header = header.unwrap();
-        assert_eq!(NetworkType::Test, header.network_type);
-        assert_eq!("verack", header.command);
+        assert_eq!(NetworkParams::TEST.magic, header.magic);
+        assert_eq!("verack", header.command.as_str());
         assert_eq!(0, header.message_size);
         assert_eq!([0x5D, 0xF6, 0xE0, 0xE2], header.hash)
     }
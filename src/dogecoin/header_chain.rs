@@ -0,0 +1,364 @@
+use super::*;
+use block_header::{AuxPow, BlockHeader};
+use hash256::Hash256;
+use pow::{ChainWork, Target};
+
+// Headers following a trusted starting point (genesis or a checkpoint), single branch only.
+// Proof of work is checked against each header's own bits and the work adds up, but difficulty
+// retargeting (DigiShield) is not checked. Until the total reaches the network's minimum chain
+// work the tip could be a cheaply mined fake, see check_minimum_work.
+
+pub struct HeaderChain {
+    pow_limit_bits: u32,
+    aux_pow_chain_id: i32,
+    min_chain_work: ChainWork,
+    start_height: u32,
+    start_hash: Hash256,
+    chain_work: ChainWork,
+    headers: Vec<BlockHeader>,
+    hashes: Vec<Hash256>,
+}
+
+impl HeaderChain {
+    // The genesis block's own work is not counted
+    pub fn new(network: &NetworkParams) -> Self {
+        Self::from_checkpoint(
+            network,
+            0,
+            Hash256::from_bytes(network.genesis_hash),
+            ChainWork::ZERO,
+        )
+    }
+
+    // chain_work is the total up to and including the checkpoint, as getblockheader reports it
+    pub fn from_checkpoint(
+        network: &NetworkParams,
+        height: u32,
+        hash: Hash256,
+        chain_work: ChainWork,
+    ) -> Self {
+        Self {
+            pow_limit_bits: network.pow_limit_bits,
+            aux_pow_chain_id: network.aux_pow_chain_id,
+            min_chain_work: ChainWork::from_le_bytes(network.min_chain_work),
+            start_height: height,
+            start_hash: hash,
+            chain_work,
+            headers: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn start_height(&self) -> u32 {
+        self.start_height
+    }
+
+    pub fn height(&self) -> u32 {
+        self.start_height + self.headers.len() as u32
+    }
+
+    pub fn tip_hash(&self) -> Hash256 {
+        self.hashes.last().copied().unwrap_or(self.start_hash)
+    }
+
+    pub fn chain_work(&self) -> ChainWork {
+        self.chain_work
+    }
+
+    pub fn check_minimum_work(&self) -> Result<(), HeaderChainError> {
+        if self.chain_work < self.min_chain_work {
+            return Err(HeaderChainError::InsufficientChainWork {
+                work: self.chain_work,
+                minimum: self.min_chain_work,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Everything connected so far, the starting block itself is not included
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    pub fn hash_at(&self, height: u32) -> Option<Hash256> {
+        match height.checked_sub(self.start_height)? {
+            0 => Some(self.start_hash),
+            offset => self.hashes.get(offset as usize - 1).copied(),
+        }
+    }
+
+    // Like CChain::GetLocator, the last 10 blocks and then exponentially sparser back to start
+    pub fn locator(&self) -> Vec<Hash256> {
+        let mut locator = Vec::new();
+        let mut height = self.height();
+        let mut step = 1;
+        while height > self.start_height {
+            locator.push(self.hash_at(height).unwrap_or(self.start_hash));
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step).max(self.start_height);
+        }
+        locator.push(self.start_hash);
+
+        locator
+    }
+
+    pub fn connect(
+        &mut self,
+        header: BlockHeader,
+        aux_pow: Option<&AuxPow>,
+    ) -> Result<Hash256, HeaderChainError> {
+        let tip = self.tip_hash();
+        if header.prev_block != tip {
+            return Err(HeaderChainError::DoesNotConnect {
+                tip,
+                prev_block: header.prev_block,
+            });
+        }
+
+        let hash = header.hash();
+        let work = self.check_proof_of_work(&header, hash, aux_pow)?;
+        self.chain_work = self.chain_work.saturating_add(work);
+        self.headers.push(header);
+        self.hashes.push(hash);
+
+        Ok(hash)
+    }
+
+    fn check_proof_of_work(
+        &self,
+        header: &BlockHeader,
+        hash: Hash256,
+        aux_pow: Option<&AuxPow>,
+    ) -> Result<ChainWork, HeaderChainError> {
+        let invalid = |e| HeaderChainError::InvalidProofOfWork(hash, e);
+        let target = Target::from_compact(header.bits).map_err(invalid)?;
+        let limit = Target::from_compact(self.pow_limit_bits).map_err(invalid)?;
+        if target > limit {
+            return Err(HeaderChainError::TargetAboveLimit {
+                bits: header.bits,
+                limit_bits: self.pow_limit_bits,
+            });
+        }
+
+        // The work of a merge mined block is done on its parent chain's header
+        let proof = match (header.is_aux_pow(), aux_pow) {
+            (false, None) => header,
+            (true, Some(aux_pow)) => {
+                if header.chain_id() != self.aux_pow_chain_id {
+                    return Err(HeaderChainError::WrongChainId {
+                        hash,
+                        chain_id: header.chain_id(),
+                        expected: self.aux_pow_chain_id,
+                    });
+                }
+                aux_pow
+                    .check(hash, self.aux_pow_chain_id)
+                    .map_err(|e| HeaderChainError::InvalidAuxPow(hash, e))?;
+                &aux_pow.parent_header
+            }
+            _ => return Err(HeaderChainError::AuxPowMismatch(hash)),
+        };
+
+        proof.check_proof_of_work(header.bits).map_err(invalid)?;
+
+        Ok(target.work())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogecoin::tests::{merge_mine, mine_child, EASY_NETWORK, MAIN_GENESIS_HEADER};
+    use block_header::VERSION_AUXPOW;
+
+    #[test]
+    fn test_connect_and_locator() {
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        assert_eq!(0, chain.height());
+        assert_eq!(vec![MAIN_GENESIS_HEADER.hash()], chain.locator());
+
+        let mut tip = chain.tip_hash();
+        for time in 0..30 {
+            tip = chain.connect(mine_child(tip, time), None).unwrap();
+        }
+        assert_eq!(30, chain.height());
+        assert_eq!(tip, chain.tip_hash());
+        assert_eq!(chain_work(30), chain.chain_work());
+        assert_eq!(Some(chain.headers()[0].hash()), chain.hash_at(1));
+        assert_eq!(None, chain.hash_at(31));
+
+        let heights: Vec<u32> = chain
+            .locator()
+            .iter()
+            .map(|hash| (0..=30).find(|&height| chain.hash_at(height) == Some(*hash)))
+            .map(Option::unwrap)
+            .collect();
+        assert_eq!(
+            vec![30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 19, 15, 7, 0],
+            heights
+        );
+
+        assert_eq!(
+            Err(HeaderChainError::DoesNotConnect {
+                tip,
+                prev_block: MAIN_GENESIS_HEADER.hash(),
+            }),
+            chain.connect(mine_child(MAIN_GENESIS_HEADER.hash(), 100), None)
+        );
+        assert_eq!(30, chain.height());
+    }
+
+    fn chain_work(headers: u32) -> ChainWork {
+        let header = Target::from_compact(EASY_NETWORK.pow_limit_bits)
+            .unwrap()
+            .work();
+        (0..headers).fold(ChainWork::ZERO, |total, _| total.saturating_add(header))
+    }
+
+    #[test]
+    fn test_minimum_work() {
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        for time in 0..2 {
+            chain
+                .connect(mine_child(chain.tip_hash(), time), None)
+                .unwrap();
+        }
+        assert_eq!(
+            Err(HeaderChainError::InsufficientChainWork {
+                work: chain_work(2),
+                minimum: chain_work(3),
+            }),
+            chain.check_minimum_work()
+        );
+
+        chain
+            .connect(mine_child(chain.tip_hash(), 2), None)
+            .unwrap();
+        assert_eq!(Ok(()), chain.check_minimum_work());
+    }
+
+    #[test]
+    fn test_from_checkpoint() {
+        let checkpoint = Hash256::from_bytes([0x42; 32]);
+        let work = Target::from_compact(0x1e0fffff).unwrap().work();
+        let mut chain = HeaderChain::from_checkpoint(&EASY_NETWORK, 1_000_000, checkpoint, work);
+        assert_eq!(vec![checkpoint], chain.locator());
+        assert_eq!(Ok(()), chain.check_minimum_work());
+
+        chain.connect(mine_child(checkpoint, 0), None).unwrap();
+        assert_eq!(1_000_001, chain.height());
+        assert_eq!(work.saturating_add(chain_work(1)), chain.chain_work());
+        assert_eq!(Some(checkpoint), chain.hash_at(1_000_000));
+        assert_eq!(None, chain.hash_at(999_999));
+        assert_eq!(vec![chain.tip_hash(), checkpoint], chain.locator());
+    }
+
+    #[test]
+    fn test_proof_of_work_checks() {
+        let genesis = MAIN_GENESIS_HEADER.hash();
+        let mut chain = HeaderChain::new(&NetworkParams::MAIN);
+
+        let too_easy = mine_child(genesis, 0);
+        assert_eq!(
+            Err(HeaderChainError::TargetAboveLimit {
+                bits: 0x2100ffff,
+                limit_bits: 0x1e0fffff,
+            }),
+            chain.connect(too_easy, None)
+        );
+
+        let unmined = BlockHeader {
+            prev_block: genesis,
+            ..MAIN_GENESIS_HEADER
+        };
+        assert!(matches!(
+            chain.connect(unmined, None),
+            Err(HeaderChainError::InvalidProofOfWork(
+                hash,
+                ProofOfWorkError::HashAboveTarget { bits: 0x1e0ffff0, .. }
+            )) if hash == unmined.hash()
+        ));
+
+        // Merge mined, the genesis block has enough work but doesn't commit to this header
+        let merge_mined = BlockHeader {
+            version: 0x0062_0000 | VERSION_AUXPOW | 1,
+            ..unmined
+        };
+        let aux_pow = AuxPow {
+            coinbase_tx: vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            parent_block_hash: genesis,
+            coinbase_branch: Vec::new(),
+            coinbase_index: 0,
+            chain_branch: Vec::new(),
+            chain_index: 0,
+            parent_header: MAIN_GENESIS_HEADER,
+        };
+        assert_eq!(
+            Err(HeaderChainError::AuxPowMismatch(merge_mined.hash())),
+            chain.connect(merge_mined, None)
+        );
+        assert_eq!(
+            Err(HeaderChainError::AuxPowMismatch(unmined.hash())),
+            chain.connect(unmined, Some(&aux_pow))
+        );
+        assert_eq!(
+            Err(HeaderChainError::InvalidAuxPow(
+                merge_mined.hash(),
+                AuxPowError::CoinbaseNotInParent
+            )),
+            chain.connect(merge_mined, Some(&aux_pow))
+        );
+        assert_eq!(0, chain.height());
+    }
+
+    #[test]
+    fn test_merge_mined_headers() {
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        let merge_mined = BlockHeader {
+            version: 0x0062_0000 | VERSION_AUXPOW | 4,
+            ..mine_child(chain.tip_hash(), 0)
+        };
+        let aux_pow = merge_mine(&merge_mined);
+        assert_eq!(
+            Ok(merge_mined.hash()),
+            chain.connect(merge_mined, Some(&aux_pow))
+        );
+
+        // The parent commits to one block only, the proof can't be reused for another
+        let other = BlockHeader {
+            time: 1,
+            prev_block: chain.tip_hash(),
+            ..merge_mined
+        };
+        assert_eq!(
+            Err(HeaderChainError::InvalidAuxPow(
+                other.hash(),
+                AuxPowError::MissingChainRoot
+            )),
+            chain.connect(other, Some(&aux_pow))
+        );
+        assert_eq!(1, chain.height());
+    }
+
+    #[test]
+    fn test_wrong_chain_id() {
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        // Valid merge mining, but for another coin's chain id
+        let other_coin = BlockHeader {
+            version: 0x0001_0000 | VERSION_AUXPOW | 4,
+            ..mine_child(chain.tip_hash(), 0)
+        };
+        assert_eq!(
+            Err(HeaderChainError::WrongChainId {
+                hash: other_coin.hash(),
+                chain_id: 0x0001,
+                expected: 0x0062,
+            }),
+            chain.connect(other_coin, Some(&merge_mine(&other_coin)))
+        );
+        assert_eq!(0, chain.height());
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use super::super::*;
+use messages::version::IpData;
+use serializer::{Decode, Encode, Reader};
+
+// addr -> peer addresses being gossiped, answer to getaddr or unsolicited relay
+
+// MAX_ADDR_TO_SEND in Dogecoin Core, bigger messages get the sender penalized
+pub const MAX_ADDR_TO_SEND: usize = 1000;
+
+// net_addr as found in addr, the version message carries IpData without the time
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TimestampedIpData {
+    pub timestamp: u32,
+    pub ip_data: IpData,
+}
+
+impl Decode for TimestampedIpData {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        serde_wire::from_reader(reader)
+    }
+}
+
+impl Encode for TimestampedIpData {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        buffer.extend(serde_wire::to_bytes(self)?);
+
+        Ok(())
+    }
+}
+
+// Extra payload is ignored, Core doesn't look past the addresses either
+#[derive(Clone, Debug, Default, NetworkSerializable, PartialEq)]
+#[wire(allow_trailing)]
+pub struct Addr {
+    #[wire(compact_vec, max = MAX_ADDR_TO_SEND)]
+    pub addresses: Vec<TimestampedIpData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use service_flags::ServiceFlags;
+
+    #[test]
+    fn test_addr_round_trip() {
+        let bytes = [
+            0x01, 0xE2, 0x15, 0x36, 0x64, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x34, 0x4D, 0xE7,
+            0x29, 0xAE, 0x0C,
+        ];
+        let message = Addr::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            Addr {
+                addresses: vec![TimestampedIpData {
+                    timestamp: 1681266146,
+                    ip_data: IpData {
+                        node_services: ServiceFlags::NODE_NETWORK | ServiceFlags::NODE_BLOOM,
+                        ip_address: "52.77.231.41".parse().unwrap(),
+                        port: 44556,
+                    },
+                }],
+            },
+            message
+        );
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(Addr::from_network_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(
+            message,
+            Addr::from_network_bytes(&[&bytes[..], &[0x00]].concat()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_addr_too_many_entries() {
+        assert!(matches!(
+            Addr::from_network_bytes(&[0xFD, 0xE9, 0x03]),
+            Err(NetworkSerializationError::TooManyEntries {
+                count: 1001,
+                limit: 1000
+            })
+        ));
+
+        let addr = Addr {
+            addresses: vec![
+                TimestampedIpData {
+                    timestamp: 0,
+                    ip_data: IpData {
+                        node_services: ServiceFlags::NONE,
+                        ip_address: "127.0.0.1".parse().unwrap(),
+                        port: 22556,
+                    },
+                };
+                MAX_ADDR_TO_SEND + 1
+            ],
+        };
+        assert!(addr.to_network_bytes().is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use super::super::*;
+use serializer::Reader;
+
+// feefilter -> minimum fee rate (in koinu per kilobyte) of transactions to be relayed to us
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeFilter {
+    pub fee_rate: u64,
+}
+
+impl NetworkSerializable<FeeFilter> for FeeFilter {
+    fn from_network_bytes(bytes: &[u8]) -> Result<FeeFilter, NetworkSerializationError> {
+        let fee_rate = Reader::new(bytes).read_u64_le()?;
+
+        Ok(FeeFilter { fee_rate })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        Ok(self.fee_rate.to_le_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_filter_round_trip() {
+        let bytes = [0xE8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let message = FeeFilter::from_network_bytes(&bytes).unwrap();
+        assert_eq!(FeeFilter { fee_rate: 1000 }, message);
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(FeeFilter::from_network_bytes(&bytes[0..7]).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use super::super::*;
+use serializer::{Reader, SerializeBytes};
+
+// filteradd -> single element to be inserted into the loaded bloom filter
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterAdd {
+    pub data: Vec<u8>,
+}
+
+impl NetworkSerializable<FilterAdd> for FilterAdd {
+    fn from_network_bytes(bytes: &[u8]) -> Result<FilterAdd, NetworkSerializationError> {
+        let data = Reader::new(bytes).read_var_bytes()?;
+
+        Ok(FilterAdd { data })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        Ok(self.data.to_dogecoin_bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_add_round_trip() {
+        let bytes = [0x03, 0x01, 0x02, 0x03];
+        let message = FilterAdd::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            FilterAdd {
+                data: vec![1, 2, 3]
+            },
+            message
+        );
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(FilterAdd::from_network_bytes(&bytes[0..3]).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use super::super::*;
+use serializer::{Reader, SerializeBytes};
+
+// filterload -> BIP37 bloom filter used to select relayed transactions
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterLoad {
+    pub filter: Vec<u8>,
+    pub hash_functions: u32,
+    pub tweak: u32,
+    pub flags: u8,
+}
+
+impl NetworkSerializable<FilterLoad> for FilterLoad {
+    fn from_network_bytes(bytes: &[u8]) -> Result<FilterLoad, NetworkSerializationError> {
+        let mut reader = Reader::new(bytes);
+        let filter = reader.read_var_bytes()?;
+        let hash_functions = reader.read_u32_le()?;
+        let tweak = reader.read_u32_le()?;
+        let flags = reader.read_u8()?;
+
+        Ok(FilterLoad {
+            filter,
+            hash_functions,
+            tweak,
+            flags,
+        })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        let mut buffer = self.filter.to_dogecoin_bytes()?;
+
+        buffer.extend_from_slice(&self.hash_functions.to_le_bytes());
+        buffer.extend_from_slice(&self.tweak.to_le_bytes());
+        buffer.push(self.flags);
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_load_round_trip() {
+        let bytes = [
+            0x02, 0xB5, 0x0F, 0x0B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ];
+        let message = FilterLoad::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            FilterLoad {
+                filter: vec![0xB5, 0x0F],
+                hash_functions: 11,
+                tweak: 0,
+                flags: 1,
+            },
+            message
+        );
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(FilterLoad::from_network_bytes(&bytes[0..11]).is_err());
+    }
+}
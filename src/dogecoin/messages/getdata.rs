@@ -0,0 +1,10 @@
+use super::super::*;
+use messages::inv::{Inventory, MAX_INV_SZ};
+
+// getdata -> request for the full objects behind previously announced inventory
+
+#[derive(Clone, Debug, Default, NetworkSerializable, PartialEq)]
+pub struct GetData {
+    #[wire(compact_vec, max = MAX_INV_SZ)]
+    pub inventory: Vec<Inventory>,
+}
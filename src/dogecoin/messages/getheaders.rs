@@ -0,0 +1,53 @@
+use super::super::*;
+use hash256::Hash256;
+
+// getheaders -> asks for up to 2000 headers following the first locator hash the peer knows
+
+// MAX_LOCATOR_SZ in Dogecoin Core, longer locators get the sender disconnected
+pub const MAX_LOCATOR_SZ: usize = 101;
+
+#[derive(Clone, Debug, NetworkSerializable, PartialEq)]
+pub struct GetHeaders {
+    pub version: u32,
+    // Newest first, see HeaderChain::locator
+    #[wire(compact_vec, max = MAX_LOCATOR_SZ)]
+    pub locator: Vec<Hash256>,
+    // All zeroes asks for as many headers as fit in one answer
+    pub hash_stop: Hash256,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_getheaders_round_trip() {
+        let mut bytes = vec![0x7F, 0x11, 0x01, 0x00, 0x01];
+        bytes.extend(NetworkParams::MAIN.genesis_hash);
+        bytes.extend([0x00; 32]);
+
+        let message = GetHeaders::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            GetHeaders {
+                version: 70015,
+                locator: vec![Hash256::from_bytes(NetworkParams::MAIN.genesis_hash)],
+                hash_stop: Hash256::ZERO,
+            },
+            message
+        );
+        assert_eq!(bytes, message.to_network_bytes().unwrap());
+
+        assert!(GetHeaders::from_network_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(matches!(
+            GetHeaders::from_network_bytes(&[&bytes[..], &[0x00]].concat()),
+            Err(NetworkSerializationError::UnknownBytes)
+        ));
+        assert!(matches!(
+            GetHeaders::from_network_bytes(&[0x7F, 0x11, 0x01, 0x00, 0x66]),
+            Err(NetworkSerializationError::TooManyEntries {
+                count: 102,
+                limit: 101
+            })
+        ));
+    }
+}
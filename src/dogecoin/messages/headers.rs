@@ -0,0 +1,144 @@
+use super::super::*;
+use block_header::{AuxPow, BlockHeader};
+use serializer::{CompactSize, Decode, Encode, Reader};
+
+// headers -> answer to getheaders, each header is followed by an always empty tx list
+
+// MAX_HEADERS_RESULTS in Dogecoin Core
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadersEntry {
+    pub header: BlockHeader,
+    // Present exactly when the header's version has the auxpow bit set
+    pub aux_pow: Option<AuxPow>,
+}
+
+impl Decode for HeadersEntry {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        let header: BlockHeader = reader.read()?;
+        let aux_pow = match header.is_aux_pow() {
+            true => Some(reader.read()?),
+            false => None,
+        };
+        if reader.read_compact_size()? != 0 {
+            return Err(NetworkSerializationError::UnknownBytes);
+        }
+
+        Ok(HeadersEntry { header, aux_pow })
+    }
+}
+
+impl Encode for HeadersEntry {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        // Decoding goes by the version bit, anything else wouldn't read back the same
+        if self.aux_pow.is_some() != self.header.is_aux_pow() {
+            return Err(NetworkSerializationError::AuxPowMismatch);
+        }
+
+        self.header.encode(buffer)?;
+        if let Some(aux_pow) = &self.aux_pow {
+            aux_pow.encode(buffer)?;
+        }
+        CompactSize(0).write(buffer);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, NetworkSerializable, PartialEq)]
+pub struct Headers {
+    #[wire(compact_vec, max = MAX_HEADERS_RESULTS)]
+    pub headers: Vec<HeadersEntry>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogecoin::tests::MAIN_GENESIS_HEADER;
+
+    #[test]
+    fn test_headers_round_trip() {
+        let mut bytes = vec![0x01];
+        bytes.extend(MAIN_GENESIS_HEADER.to_network_bytes().unwrap());
+        bytes.push(0x00);
+
+        let message = Headers::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            Headers {
+                headers: vec![HeadersEntry {
+                    header: MAIN_GENESIS_HEADER,
+                    aux_pow: None,
+                }],
+            },
+            message
+        );
+        assert_eq!(bytes, message.to_network_bytes().unwrap());
+
+        assert!(matches!(
+            Headers::from_network_bytes(&[&bytes[..], &[0x00]].concat()),
+            Err(NetworkSerializationError::UnknownBytes)
+        ));
+
+        // Headers never come with transactions
+        *bytes.last_mut().unwrap() = 0x01;
+        assert!(Headers::from_network_bytes(&bytes).is_err());
+        assert!(matches!(
+            Headers::from_network_bytes(&[0xFD, 0xD1, 0x07]),
+            Err(NetworkSerializationError::TooManyEntries {
+                count: 2001,
+                limit: 2000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_headers_with_aux_pow() {
+        let header = BlockHeader {
+            version: 0x0062_0104,
+            ..MAIN_GENESIS_HEADER
+        };
+        let aux_pow = AuxPow {
+            coinbase_tx: vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            parent_block_hash: MAIN_GENESIS_HEADER.hash(),
+            coinbase_branch: Vec::new(),
+            coinbase_index: 0,
+            chain_branch: Vec::new(),
+            chain_index: 0,
+            parent_header: MAIN_GENESIS_HEADER,
+        };
+        let message = Headers {
+            headers: vec![HeadersEntry {
+                header,
+                aux_pow: Some(aux_pow.clone()),
+            }],
+        };
+
+        let bytes = message.to_network_bytes().unwrap();
+        assert_eq!(1 + 80 + 10 + 32 + 1 + 4 + 1 + 4 + 80 + 1, bytes.len());
+        assert_eq!(message, Headers::from_network_bytes(&bytes).unwrap());
+        assert_eq!(0x62, header.chain_id());
+
+        // Would decode as something else entirely, so it isn't encoded at all
+        let without_aux_pow = Headers {
+            headers: vec![HeadersEntry {
+                header,
+                aux_pow: None,
+            }],
+        };
+        assert!(matches!(
+            without_aux_pow.to_network_bytes(),
+            Err(NetworkSerializationError::AuxPowMismatch)
+        ));
+        let without_version_bit = Headers {
+            headers: vec![HeadersEntry {
+                header: MAIN_GENESIS_HEADER,
+                aux_pow: Some(aux_pow),
+            }],
+        };
+        assert!(matches!(
+            without_version_bit.to_network_bytes(),
+            Err(NetworkSerializationError::AuxPowMismatch)
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use super::super::*;
+use hash256::Hash256;
+use serializer::{Decode, Encode, Reader};
+
+// inv -> peer announces transactions or blocks it has, we may ask for them with getdata
+
+// MAX_INV_SZ in Dogecoin Core, bigger inv/getdata/notfound get the sender penalized
+pub const MAX_INV_SZ: usize = 50_000;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum InvType {
+    Error,
+    Tx,
+    Block,
+    FilteredBlock,
+    CmpctBlock,
+    Unknown(u32),
+}
+
+impl From<u32> for InvType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => InvType::Error,
+            1 => InvType::Tx,
+            2 => InvType::Block,
+            3 => InvType::FilteredBlock,
+            4 => InvType::CmpctBlock,
+            _ => InvType::Unknown(value),
+        }
+    }
+}
+
+impl From<InvType> for u32 {
+    fn from(value: InvType) -> Self {
+        match value {
+            InvType::Error => 0,
+            InvType::Tx => 1,
+            InvType::Block => 2,
+            InvType::FilteredBlock => 3,
+            InvType::CmpctBlock => 4,
+            InvType::Unknown(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for InvType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvType::Error => write!(f, "ERROR"),
+            InvType::Tx => write!(f, "MSG_TX"),
+            InvType::Block => write!(f, "MSG_BLOCK"),
+            InvType::FilteredBlock => write!(f, "MSG_FILTERED_BLOCK"),
+            InvType::CmpctBlock => write!(f, "MSG_CMPCT_BLOCK"),
+            InvType::Unknown(value) => write!(f, "UNKNOWN({:#x})", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Inventory {
+    pub inv_type: InvType,
+    pub hash: Hash256,
+}
+
+impl Decode for Inventory {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        let inv_type = reader.read_u32_le()?.into();
+        let hash = reader.read()?;
+
+        Ok(Inventory { inv_type, hash })
+    }
+}
+
+impl Encode for Inventory {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        u32::from(self.inv_type).encode(buffer)?;
+        self.hash.encode(buffer)
+    }
+}
+
+#[derive(Clone, Debug, Default, NetworkSerializable, PartialEq)]
+pub struct Inv {
+    #[wire(compact_vec, max = MAX_INV_SZ)]
+    pub inventory: Vec<Inventory>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inv_round_trip() {
+        let mut bytes = vec![0x02, 0x01, 0x00, 0x00, 0x00];
+        bytes.extend((0..32).map(|byte| byte as u8));
+        bytes.extend([0x07, 0x00, 0x00, 0x40]);
+        bytes.extend([0xFF; 32]);
+
+        let message = Inv::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            Inv {
+                inventory: vec![
+                    Inventory {
+                        inv_type: InvType::Tx,
+                        hash: Hash256::from_hex(
+                            "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100"
+                        ),
+                    },
+                    Inventory {
+                        inv_type: InvType::Unknown(0x4000_0007),
+                        hash: Hash256::from_bytes([0xFF; 32]),
+                    },
+                ],
+            },
+            message
+        );
+        assert_eq!(bytes, message.to_network_bytes().unwrap());
+        assert_eq!(
+            "UNKNOWN(0x40000007)",
+            message.inventory[1].inv_type.to_string()
+        );
+
+        assert!(Inv::from_network_bytes(&bytes[..bytes.len() - 1]).is_err());
+        bytes.push(0x00);
+        assert!(matches!(
+            Inv::from_network_bytes(&bytes),
+            Err(NetworkSerializationError::UnknownBytes)
+        ));
+    }
+
+    #[test]
+    fn test_inv_type_values() {
+        for value in 0..=5 {
+            assert_eq!(value, u32::from(InvType::from(value)));
+        }
+        assert_eq!(InvType::Block, InvType::from(2));
+        assert_eq!("MSG_FILTERED_BLOCK", InvType::FilteredBlock.to_string());
+        assert_eq!("MSG_CMPCT_BLOCK", InvType::from(4).to_string());
+    }
+
+    #[test]
+    fn test_inv_too_many_entries() {
+        assert!(matches!(
+            Inv::from_network_bytes(&[0xFD, 0x51, 0xC3]),
+            Err(NetworkSerializationError::TooManyEntries {
+                count: 50_001,
+                limit: 50_000
+            })
+        ));
+
+        let inv = Inv {
+            inventory: vec![
+                Inventory {
+                    inv_type: InvType::Tx,
+                    hash: Hash256::ZERO,
+                };
+                MAX_INV_SZ + 1
+            ],
+        };
+        assert!(inv.to_network_bytes().is_err());
+    }
+}
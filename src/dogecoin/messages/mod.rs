@@ -1,2 +1,296 @@
+pub mod addr;
+pub mod feefilter;
+pub mod filteradd;
+pub mod filterload;
+pub mod getdata;
+pub mod getheaders;
+pub mod headers;
+pub mod inv;
+pub mod notfound;
+pub mod ping;
+pub mod pong;
+pub mod reject;
+pub mod sendcmpct;
 pub mod verack;
 pub mod version;
+
+use super::*;
+use addr::Addr;
+use feefilter::FeeFilter;
+use filteradd::FilterAdd;
+use filterload::FilterLoad;
+use getdata::GetData;
+use getheaders::GetHeaders;
+use header::{Command, Header};
+use headers::Headers;
+use inv::Inv;
+use notfound::NotFound;
+use ping::Ping;
+use pong::Pong;
+use reject::Reject;
+use sendcmpct::SendCmpct;
+use version::VersionMessageData;
+
+// Payload kept exactly as received, for commands whose contents are not interpreted (yet)
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawPayload(pub Vec<u8>);
+
+impl NetworkSerializable<RawPayload> for RawPayload {
+    fn from_network_bytes(bytes: &[u8]) -> Result<RawPayload, NetworkSerializationError> {
+        Ok(RawPayload(bytes.to_vec()))
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        Ok(self.0.clone())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Addr(Addr),
+    Block(RawPayload),
+    FeeFilter(FeeFilter),
+    FilterAdd(FilterAdd),
+    FilterClear,
+    FilterLoad(FilterLoad),
+    GetAddr,
+    GetBlocks(RawPayload),
+    GetData(GetData),
+    GetHeaders(GetHeaders),
+    Headers(Headers),
+    Inv(Inv),
+    MemPool,
+    MerkleBlock(RawPayload),
+    NotFound(NotFound),
+    Ping(Ping),
+    Pong(Pong),
+    Reject(Reject),
+    SendCmpct(SendCmpct),
+    SendHeaders,
+    Tx(RawPayload),
+    Verack,
+    Version(VersionMessageData),
+    Unknown { command: String, payload: Vec<u8> },
+}
+
+impl Message {
+    pub fn command(&self) -> &str {
+        match self {
+            Message::Addr(_) => "addr",
+            Message::Block(_) => "block",
+            Message::FeeFilter(_) => "feefilter",
+            Message::FilterAdd(_) => "filteradd",
+            Message::FilterClear => "filterclear",
+            Message::FilterLoad(_) => "filterload",
+            Message::GetAddr => "getaddr",
+            Message::GetBlocks(_) => "getblocks",
+            Message::GetData(_) => "getdata",
+            Message::GetHeaders(_) => "getheaders",
+            Message::Headers(_) => "headers",
+            Message::Inv(_) => "inv",
+            Message::MemPool => "mempool",
+            Message::MerkleBlock(_) => "merkleblock",
+            Message::NotFound(_) => "notfound",
+            Message::Ping(_) => "ping",
+            Message::Pong(_) => "pong",
+            Message::Reject(_) => "reject",
+            Message::SendCmpct(_) => "sendcmpct",
+            Message::SendHeaders => "sendheaders",
+            Message::Tx(_) => "tx",
+            Message::Verack => "verack",
+            Message::Version(_) => "version",
+            Message::Unknown { command, .. } => command.as_str(),
+        }
+    }
+
+    pub fn from_payload(command: &str, payload: &[u8]) -> Result<Self, NetworkSerializationError> {
+        let message = match command {
+            "addr" => Message::Addr(Addr::from_network_bytes(payload)?),
+            "block" => Message::Block(RawPayload::from_network_bytes(payload)?),
+            "feefilter" => Message::FeeFilter(FeeFilter::from_network_bytes(payload)?),
+            "filteradd" => Message::FilterAdd(FilterAdd::from_network_bytes(payload)?),
+            "filterclear" => Message::FilterClear,
+            "filterload" => Message::FilterLoad(FilterLoad::from_network_bytes(payload)?),
+            "getaddr" => Message::GetAddr,
+            "getblocks" => Message::GetBlocks(RawPayload::from_network_bytes(payload)?),
+            "getdata" => Message::GetData(GetData::from_network_bytes(payload)?),
+            "getheaders" => Message::GetHeaders(GetHeaders::from_network_bytes(payload)?),
+            "headers" => Message::Headers(Headers::from_network_bytes(payload)?),
+            "inv" => Message::Inv(Inv::from_network_bytes(payload)?),
+            "mempool" => Message::MemPool,
+            "merkleblock" => Message::MerkleBlock(RawPayload::from_network_bytes(payload)?),
+            "notfound" => Message::NotFound(NotFound::from_network_bytes(payload)?),
+            "ping" => Message::Ping(Ping::from_network_bytes(payload)?),
+            "pong" => Message::Pong(Pong::from_network_bytes(payload)?),
+            "reject" => Message::Reject(Reject::from_network_bytes(payload)?),
+            "sendcmpct" => Message::SendCmpct(SendCmpct::from_network_bytes(payload)?),
+            "sendheaders" => Message::SendHeaders,
+            "tx" => Message::Tx(RawPayload::from_network_bytes(payload)?),
+            "verack" => Message::Verack,
+            "version" => Message::Version(VersionMessageData::from_network_bytes(payload)?),
+            _ => Message::Unknown {
+                command: command.to_string(),
+                payload: payload.to_vec(),
+            },
+        };
+
+        Ok(message)
+    }
+
+    pub fn to_payload(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        match self {
+            Message::FilterClear
+            | Message::GetAddr
+            | Message::MemPool
+            | Message::SendHeaders
+            | Message::Verack => Ok(Vec::new()),
+            Message::Block(payload)
+            | Message::GetBlocks(payload)
+            | Message::MerkleBlock(payload)
+            | Message::Tx(payload) => payload.to_network_bytes(),
+            Message::Addr(data) => data.to_network_bytes(),
+            Message::FeeFilter(data) => data.to_network_bytes(),
+            Message::FilterAdd(data) => data.to_network_bytes(),
+            Message::FilterLoad(data) => data.to_network_bytes(),
+            Message::GetData(data) => data.to_network_bytes(),
+            Message::GetHeaders(data) => data.to_network_bytes(),
+            Message::Headers(data) => data.to_network_bytes(),
+            Message::Inv(data) => data.to_network_bytes(),
+            Message::NotFound(data) => data.to_network_bytes(),
+            Message::Ping(data) => data.to_network_bytes(),
+            Message::Pong(data) => data.to_network_bytes(),
+            Message::Reject(data) => data.to_network_bytes(),
+            Message::SendCmpct(data) => data.to_network_bytes(),
+            Message::Version(data) => data.to_network_bytes(),
+            Message::Unknown { payload, .. } => Ok(payload.clone()),
+        }
+    }
+
+    pub fn to_network_bytes(
+        &self,
+        network: &NetworkParams,
+    ) -> Result<Vec<u8>, NetworkSerializationError> {
+        let payload = self.to_payload()?;
+        let header = Header {
+            magic: network.magic,
+            command: Command::new(self.command())?,
+            message_size: payload.len(),
+            hash: [0; 4],
+        };
+
+        let mut bytes = header.to_network_bytes(&payload)?;
+        bytes.extend(payload);
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_verack_frame() {
+        let bytes = Message::Verack
+            .to_network_bytes(&NetworkParams::TEST)
+            .unwrap();
+        assert_eq!(
+            &[
+                0xFC, 0xC1, 0xB7, 0xDC, b'v', b'e', b'r', b'a', b'c', b'k', 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5D, 0xF6, 0xE0, 0xE2
+            ],
+            bytes.as_slice()
+        );
+    }
+
+    #[test]
+    fn test_parse_version_payload() {
+        let message =
+            Message::from_payload("version", &crate::dogecoin::tests::VERSION_MESSAGE).unwrap();
+        assert_eq!("version", message.command());
+        assert_eq!(
+            crate::dogecoin::tests::VERSION_MESSAGE.as_slice(),
+            message.to_payload().unwrap().as_slice()
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_payload() {
+        let message = Message::from_payload("alert", &[0x01, 0x02]).unwrap();
+        assert_eq!(
+            Message::Unknown {
+                command: "alert".to_string(),
+                payload: vec![0x01, 0x02],
+            },
+            message
+        );
+        assert_eq!("alert", message.command());
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        const COMMANDS: [&str; 23] = [
+            "addr",
+            "block",
+            "feefilter",
+            "filteradd",
+            "filterclear",
+            "filterload",
+            "getaddr",
+            "getblocks",
+            "getdata",
+            "getheaders",
+            "headers",
+            "inv",
+            "mempool",
+            "merkleblock",
+            "notfound",
+            "ping",
+            "pong",
+            "reject",
+            "sendcmpct",
+            "sendheaders",
+            "tx",
+            "verack",
+            "version",
+        ];
+        const PAYLOADS: [(&str, &[u8]); 13] = [
+            ("addr", &[0x00]),
+            ("feefilter", &[0xE8, 0x03, 0, 0, 0, 0, 0, 0]),
+            ("filteradd", &[0x01, 0xAA]),
+            ("filterload", &[0x01, 0xAA, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x00]),
+            ("getdata", &[0x00]),
+            (
+                "getheaders",
+                &[
+                    0x7F, 0x11, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                ],
+            ),
+            ("headers", &[0x00]),
+            ("inv", &[0x00]),
+            ("notfound", &[0x00]),
+            ("ping", &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]),
+            ("pong", &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]),
+            ("reject", &[0x02, b't', b'x', 0x10, 0x00]),
+            ("sendcmpct", &[0x00, 0x01, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+
+        for command in COMMANDS {
+            let payload: &[u8] = match command {
+                "version" => &crate::dogecoin::tests::VERSION_MESSAGE,
+                "filterclear" | "getaddr" | "mempool" | "sendheaders" | "verack" => &[],
+                _ => PAYLOADS
+                    .iter()
+                    .find(|(name, _)| *name == command)
+                    .map(|(_, payload)| *payload)
+                    .unwrap_or(&[0xDE, 0xAD, 0xBE, 0xEF]),
+            };
+
+            let message = Message::from_payload(command, payload).unwrap();
+            assert!(!matches!(message, Message::Unknown { .. }), "{}", command);
+            assert_eq!(command, message.command());
+            assert_eq!(payload, message.to_payload().unwrap().as_slice());
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use super::super::*;
+use messages::inv::{Inventory, MAX_INV_SZ};
+
+// notfound -> answer to getdata listing the objects the peer could not serve
+
+#[derive(Clone, Debug, Default, NetworkSerializable, PartialEq)]
+pub struct NotFound {
+    #[wire(compact_vec, max = MAX_INV_SZ)]
+    pub inventory: Vec<Inventory>,
+}
@@ -0,0 +1,41 @@
+use super::super::*;
+use serializer::Reader;
+
+// ping -> liveness probe, peer has to answer with pong carrying the same nonce
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ping {
+    pub nonce: u64,
+}
+
+impl NetworkSerializable<Ping> for Ping {
+    fn from_network_bytes(bytes: &[u8]) -> Result<Ping, NetworkSerializationError> {
+        let nonce = Reader::new(bytes).read_u64_le()?;
+
+        Ok(Ping { nonce })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        Ok(self.nonce.to_le_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_round_trip() {
+        let bytes = [0x45, 0xDF, 0x74, 0xAF, 0xC8, 0x94, 0x63, 0xF8];
+        let message = Ping::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            Ping {
+                nonce: 17898312933758525253
+            },
+            message
+        );
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(Ping::from_network_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use super::super::*;
+use serializer::Reader;
+
+// pong -> answer to ping, echoes its nonce
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pong {
+    pub nonce: u64,
+}
+
+impl NetworkSerializable<Pong> for Pong {
+    fn from_network_bytes(bytes: &[u8]) -> Result<Pong, NetworkSerializationError> {
+        let nonce = Reader::new(bytes).read_u64_le()?;
+
+        Ok(Pong { nonce })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        Ok(self.nonce.to_le_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pong_round_trip() {
+        let bytes = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let message = Pong::from_network_bytes(&bytes).unwrap();
+        assert_eq!(Pong { nonce: 1 }, message);
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(Pong::from_network_bytes(&bytes[0..4]).is_err());
+    }
+}
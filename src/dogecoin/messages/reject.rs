@@ -0,0 +1,90 @@
+use super::super::*;
+use serializer::{Reader, SerializeBytes};
+
+// reject -> peer refused one of our messages, optionally pointing at the tx/block hash
+
+pub const REJECT_MALFORMED: u8 = 0x01;
+pub const REJECT_INVALID: u8 = 0x10;
+pub const REJECT_OBSOLETE: u8 = 0x11;
+pub const REJECT_DUPLICATE: u8 = 0x12;
+pub const REJECT_NONSTANDARD: u8 = 0x40;
+pub const REJECT_DUST: u8 = 0x41;
+pub const REJECT_INSUFFICIENTFEE: u8 = 0x42;
+pub const REJECT_CHECKPOINT: u8 = 0x43;
+
+const HASH_SIZE: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reject {
+    pub message: Vec<u8>,
+    pub code: u8,
+    pub reason: Vec<u8>,
+    pub data: Option<[u8; HASH_SIZE]>,
+}
+
+impl NetworkSerializable<Reject> for Reject {
+    fn from_network_bytes(bytes: &[u8]) -> Result<Reject, NetworkSerializationError> {
+        let mut reader = Reader::new(bytes);
+        let message = reader.read_var_str()?;
+        let code = reader.read_u8()?;
+        let reason = reader.read_var_str()?;
+
+        let data = match reader.remaining() {
+            0 => None,
+            HASH_SIZE => Some(reader.read_array()?),
+            _ => return Err(NetworkSerializationError::UnknownBytes),
+        };
+
+        Ok(Reject {
+            message,
+            code,
+            reason,
+            data,
+        })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        let mut buffer = Vec::new();
+
+        buffer.extend_from_slice(&self.message.to_dogecoin_bytes()?);
+        buffer.push(self.code);
+        buffer.extend_from_slice(&self.reason.to_dogecoin_bytes()?);
+        if let Some(data) = &self.data {
+            buffer.extend_from_slice(data);
+        }
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_round_trip() {
+        let bytes = [
+            0x07, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x11, 0x04, b'o', b'l', b'd', b'!',
+        ];
+        let message = Reject::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            Reject {
+                message: b"version".to_vec(),
+                code: REJECT_OBSOLETE,
+                reason: b"old!".to_vec(),
+                data: None,
+            },
+            message
+        );
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        let mut bytes = bytes.to_vec();
+        bytes.extend_from_slice(&[0xAB; 32]);
+        let message = Reject::from_network_bytes(&bytes).unwrap();
+        assert_eq!(Some([0xAB; 32]), message.data);
+        assert_eq!(bytes, message.to_network_bytes().unwrap());
+
+        bytes.pop();
+        assert!(Reject::from_network_bytes(&bytes).is_err());
+    }
+}
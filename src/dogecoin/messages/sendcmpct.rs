@@ -0,0 +1,54 @@
+use std::mem::size_of;
+
+use super::super::*;
+use serializer::Reader;
+
+// sendcmpct -> BIP152 compact block relay negotiation
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SendCmpct {
+    pub announce: bool,
+    pub version: u64,
+}
+
+const SEND_CMPCT_SIZE: usize = size_of::<bool>() + size_of::<u64>();
+
+impl NetworkSerializable<SendCmpct> for SendCmpct {
+    fn from_network_bytes(bytes: &[u8]) -> Result<SendCmpct, NetworkSerializationError> {
+        let mut reader = Reader::new(bytes);
+        let announce = reader.read_bool()?;
+        let version = reader.read_u64_le()?;
+
+        Ok(SendCmpct { announce, version })
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        let mut buffer = Vec::with_capacity(SEND_CMPCT_SIZE);
+
+        buffer.push(self.announce.into());
+        buffer.extend_from_slice(&self.version.to_le_bytes());
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_cmpct_round_trip() {
+        let bytes = [0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let message = SendCmpct::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            SendCmpct {
+                announce: true,
+                version: 1
+            },
+            message
+        );
+        assert_eq!(&bytes, message.to_network_bytes().unwrap().as_slice());
+
+        assert!(SendCmpct::from_network_bytes(&bytes[0..8]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use super::super::*;
-use header::Header;
 use header::HEADER_SIZE;
+use header::{Command, Header};
+use serializer::Reader;
 
 // verack -> version ack
 
@@ -9,11 +10,11 @@ pub struct Verack {
 }
 
 impl Verack {
-    pub fn new(network_type: NetworkType) -> Self {
+    pub fn new(network: &NetworkParams) -> Self {
         Verack {
             header: Header {
-                network_type,
-                command: "verack".to_string(),
+                magic: network.magic,
+                command: Command::from_static("verack"),
                 message_size: 0,
                 hash: [0; 4],
             },
@@ -23,11 +24,10 @@ impl Verack {
 
 impl NetworkSerializable<Verack> for Verack {
     fn from_network_bytes(bytes: &[u8]) -> Result<Verack, NetworkSerializationError> {
-        if bytes.len() < HEADER_SIZE {
-            return Err(NetworkSerializationError::UnknownBytes);
-        }
+        let mut reader = Reader::new(bytes);
+
         Ok(Verack {
-            header: Header::from_network_bytes(&bytes[0..24])?,
+            header: Header::from_network_bytes(reader.read_bytes(HEADER_SIZE)?)?,
         })
     }
 
@@ -42,13 +42,12 @@ mod tests {
 
     #[test]
     fn test_build_verack_message() {
-        let message = Verack::new(NetworkType::Test);
+        let message = Verack::new(&NetworkParams::TEST);
         let bytes = message.to_network_bytes();
         assert_eq!(
             &[
-                0xFC, 0xC1, 0xB7, 0xDC, 'v' as u8, 'e' as u8, 'r' as u8, 'a' as u8, 'c' as u8,
-                'k' as u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5D, 0xF6,
-                0xE0, 0xE2
+                0xFC, 0xC1, 0xB7, 0xDC, b'v', b'e', b'r', b'a', b'c', b'k', 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5D, 0xF6, 0xE0, 0xE2
             ],
             bytes.unwrap().as_slice()
         );
@@ -1,32 +1,43 @@
-use std::mem::size_of;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::SystemTime;
+
+use std::fmt;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::{self, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::super::*;
 
 use errors::*;
-use header::{Header, HEADER_SIZE};
-use serializer::{
-    be_slice_to_u16, calculate_size_of_serialized_string_and_length_bytes, slice_to_ip_address,
-    slice_to_u32, slice_to_u64, SerializeString,
-};
+use header::{Command, Header, HEADER_SIZE};
+use serializer::{is_safe_char, Reader};
+use service_flags::ServiceFlags;
+use user_agent::UserAgent;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct IpData {
-    pub node_services: u64,
-    pub ip_address: IpAddress,
+    pub node_services: ServiceFlags,
+    #[serde(with = "serde_wire::ip_address")]
+    pub ip_address: IpAddr,
+    #[serde(with = "serde_wire::be_u16")]
     pub port: u16,
 }
 
-#[derive(Debug, PartialEq)]
+// Fields missing from older protocol versions are None, serializing fails when they don't
+// match protocol_version since the payload would shift
+#[derive(Clone, Debug, PartialEq)]
 pub struct VersionMessageData {
     pub protocol_version: u32,
-    pub local_node_services: u64,
+    pub local_node_services: ServiceFlags,
     pub unix_timestamp: u64,
     pub node_ip_data: IpData,
-    pub our_ip_data: IpData,
-    pub nonce: u64,
-    pub client_name: String,
-    pub node_starting_height: u32,
-    pub relay_transactions: bool,
+    pub our_ip_data: Option<IpData>,
+    pub nonce: Option<u64>,
+    // Raw bytes as sent by the peer, see user_agent() for a validated view
+    pub client_name: Option<Vec<u8>>,
+    pub node_starting_height: Option<u32>,
+    pub relay_transactions: Option<bool>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -35,126 +46,319 @@ pub struct Version {
     pub data: VersionMessageData,
 }
 
-const IP_DATA_SIZE: usize = 26;
+pub const PROTOCOL_VERSION: u32 = 70015;
+
+// Oldest version whose messages still carry a checksum, see MIN_PROTO_VERSION in Core
+pub const MIN_PROTOCOL_VERSION: u32 = 209;
+
+// MAX_SUBVERSION_LENGTH in Dogecoin Core
+pub const MAX_USER_AGENT_LENGTH: usize = 256;
+
+pub const DEFAULT_USER_AGENT: &str = "/Shibetoshi:1.14.6/";
+
+// addr_from, nonce, user agent and start height are sent since this version
+pub const ADDRESS_FROM_VERSION: u32 = 106;
+
+// BIP37 relay flag, optional even for newer peers
+pub const RELAY_VERSION: u32 = 70001;
 
-const DATA_SIZE_WITHOUT_CLIENT_NAME: usize = size_of::<u32>()
-    + size_of::<u64>()
-    + size_of::<u64>()
-    + 2 * IP_DATA_SIZE
-    + size_of::<u64>()
-    + size_of::<u32>()
-    + size_of::<bool>();
+const VERSION_FIELD_COUNT: usize = 9;
 
 impl Version {
     pub fn new(
-        network_type: NetworkType,
+        network: &NetworkParams,
         unix_timestamp: u64,
-        target_ip_address: &str,
-        port: u16,
+        target_address: SocketAddr,
         nonce: u64,
         client_name: &str,
-    ) -> Result<Self, IntegerParsingFailure> {
-        const PROTOCOL_VERSION: u32 = 70015;
-
-        const NODE_NETWORK: u64 = 1;
-        const NODE_BLOOM: u64 = 4;
-        const LOCAL_NODE_SERVICES: u64 = NODE_NETWORK | NODE_BLOOM;
-
-        let octets = string_to_ip(target_ip_address);
-        if octets.is_none() {
-            return Err(IntegerParsingFailure);
-        }
-        let octets = octets.unwrap();
+    ) -> Result<Self, VersionBuildError> {
+        let data = VersionBuilder::new()
+            .unix_timestamp(unix_timestamp)
+            .receiver(IpData {
+                node_services: ServiceFlags::NODE_NETWORK,
+                ip_address: target_address.ip(),
+                port: target_address.port(),
+            })
+            .nonce_source(NonceSource::Fixed(nonce))
+            .user_agent(client_name)
+            .build()?;
 
         Ok(Version {
             header: Header {
-                network_type,
-                command: "version".to_string(),
+                magic: network.magic,
+                command: Command::from_static("version"),
                 message_size: 0,
                 hash: [0; 4],
             },
-            data: VersionMessageData {
-                protocol_version: PROTOCOL_VERSION,
-                local_node_services: LOCAL_NODE_SERVICES,
-                unix_timestamp,
-                node_ip_data: IpData {
-                    node_services: NODE_NETWORK,
-                    ip_address: [
-                        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, octets[0], octets[1], octets[2],
-                        octets[3],
-                    ],
-                    port,
-                },
-                our_ip_data: IpData {
-                    node_services: 5,
-                    ip_address: [0; 16],
-                    port: 0,
-                },
-                nonce,
-                client_name: client_name.to_string(),
-                node_starting_height: 0,
-                relay_transactions: true,
-            },
+            data,
         })
     }
 }
 
-impl NetworkSerializable<IpData> for IpData {
-    fn from_network_bytes(bytes: &[u8]) -> Result<IpData, NetworkSerializationError> {
-        if bytes.len() < IP_DATA_SIZE {
-            return Err(NetworkSerializationError::BufferTooShort);
+#[derive(Clone, Debug, PartialEq)]
+pub enum NonceSource {
+    Random,
+    Fixed(u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionBuilder {
+    advertised_address: IpData,
+    nonce_source: NonceSource,
+    protocol_version: u32,
+    receiver: Option<IpData>,
+    relay_transactions: bool,
+    services: ServiceFlags,
+    start_height: u32,
+    unix_timestamp: Option<u64>,
+    user_agent: String,
+}
+
+impl Default for VersionBuilder {
+    fn default() -> Self {
+        Self {
+            advertised_address: IpData {
+                node_services: ServiceFlags::NODE_NETWORK | ServiceFlags::NODE_BLOOM,
+                ip_address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                port: 0,
+            },
+            nonce_source: NonceSource::Random,
+            protocol_version: PROTOCOL_VERSION,
+            receiver: None,
+            relay_transactions: true,
+            services: ServiceFlags::NODE_NETWORK | ServiceFlags::NODE_BLOOM,
+            start_height: 0,
+            unix_timestamp: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
         }
+    }
+}
 
-        let node_services =
-            slice_to_u64(&bytes[0..8]).ok_or(NetworkSerializationError::UnknownBytes)?;
-        let ip_address =
-            slice_to_ip_address(&bytes[8..24]).ok_or(NetworkSerializationError::UnknownBytes)?;
-        let port =
-            be_slice_to_u16(&bytes[24..26]).ok_or(NetworkSerializationError::UnknownBytes)?;
-
-        Ok(IpData {
-            node_services,
-            ip_address,
-            port,
-        })
+impl VersionBuilder {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
-        let mut buffer = Vec::new();
+    pub fn protocol_version(mut self, protocol_version: u32) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
+    pub fn services(mut self, services: ServiceFlags) -> Self {
+        self.services = services;
+        self
+    }
+
+    // Current time is used when not set
+    pub fn unix_timestamp(mut self, unix_timestamp: u64) -> Self {
+        self.unix_timestamp = Some(unix_timestamp);
+        self
+    }
+
+    // Address of the remote node, agents fill it with the peer address when not set
+    pub fn receiver(mut self, receiver: IpData) -> Self {
+        self.receiver = Some(receiver);
+        self
+    }
+
+    pub fn advertised_address(mut self, advertised_address: IpData) -> Self {
+        self.advertised_address = advertised_address;
+        self
+    }
+
+    pub fn nonce_source(mut self, nonce_source: NonceSource) -> Self {
+        self.nonce_source = nonce_source;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    pub fn start_height(mut self, start_height: u32) -> Self {
+        self.start_height = start_height;
+        self
+    }
+
+    pub fn relay_transactions(mut self, relay_transactions: bool) -> Self {
+        self.relay_transactions = relay_transactions;
+        self
+    }
+
+    pub fn get_protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
+
+    pub fn build(&self) -> Result<VersionMessageData, VersionBuildError> {
+        let receiver = self.receiver.clone().unwrap_or(IpData {
+            node_services: ServiceFlags::NODE_NETWORK,
+            ip_address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            port: 0,
+        });
+
+        self.build_with_receiver(receiver)
+    }
+
+    pub fn build_for_peer(
+        &self,
+        peer_address: SocketAddr,
+    ) -> Result<VersionMessageData, VersionBuildError> {
+        let receiver = self.receiver.clone().unwrap_or(IpData {
+            node_services: ServiceFlags::NODE_NETWORK,
+            ip_address: peer_address.ip(),
+            port: peer_address.port(),
+        });
+
+        self.build_with_receiver(receiver)
+    }
 
-        buffer.reserve(size_of::<IpData>());
+    fn build_with_receiver(
+        &self,
+        receiver: IpData,
+    ) -> Result<VersionMessageData, VersionBuildError> {
+        if self.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(VersionBuildError::ProtocolVersionTooLow(
+                self.protocol_version,
+            ));
+        }
+        if self.user_agent.len() > MAX_USER_AGENT_LENGTH {
+            return Err(VersionBuildError::UserAgentTooLong(self.user_agent.len()));
+        }
+        // Peers would strip these through SanitizeString anyway
+        let user_agent = self.user_agent.as_bytes();
+        if let Some(offset) = user_agent.iter().position(|byte| !is_safe_char(*byte)) {
+            return Err(VersionBuildError::InvalidUserAgent(
+                UserAgentError::NonPrintable {
+                    offset,
+                    byte: user_agent[offset],
+                },
+            ));
+        }
 
-        buffer.extend_from_slice(&self.node_services.to_le_bytes());
-        buffer.extend_from_slice(self.ip_address.as_slice());
-        buffer.extend_from_slice(&self.port.to_be_bytes());
+        let unix_timestamp = match self.unix_timestamp {
+            Some(unix_timestamp) => unix_timestamp,
+            None => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs(),
+        };
+        let nonce = match self.nonce_source {
+            NonceSource::Random => rand::random(),
+            NonceSource::Fixed(nonce) => nonce,
+        };
 
-        Ok(buffer)
+        Ok(VersionMessageData {
+            protocol_version: self.protocol_version,
+            local_node_services: self.services,
+            unix_timestamp,
+            node_ip_data: receiver,
+            our_ip_data: Some(self.advertised_address.clone()),
+            nonce: Some(nonce),
+            client_name: Some(self.user_agent.as_bytes().to_vec()),
+            node_starting_height: Some(self.start_height),
+            relay_transactions: (self.protocol_version >= RELAY_VERSION)
+                .then_some(self.relay_transactions),
+        })
     }
 }
 
-impl NetworkSerializable<VersionMessageData> for VersionMessageData {
-    fn from_network_bytes(bytes: &[u8]) -> Result<VersionMessageData, NetworkSerializationError> {
-        if bytes.len() < DATA_SIZE_WITHOUT_CLIENT_NAME + 1 {
-            return Err(NetworkSerializationError::BufferTooShort);
+impl VersionMessageData {
+    pub fn user_agent(&self) -> Option<Result<UserAgent, UserAgentError>> {
+        self.client_name.as_deref().map(UserAgent::parse)
+    }
+}
+
+// Mirrors VersionMessageDataVisitor
+impl Serialize for VersionMessageData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let has_address_from = self.protocol_version >= ADDRESS_FROM_VERSION;
+        for (name, present) in [
+            ("our_ip_data", self.our_ip_data.is_some()),
+            ("nonce", self.nonce.is_some()),
+            ("client_name", self.client_name.is_some()),
+            ("node_starting_height", self.node_starting_height.is_some()),
+        ] {
+            if present != has_address_from {
+                return Err(ser::Error::custom(format!(
+                    "{} must {}be set for protocol version {}",
+                    name,
+                    if present { "not " } else { "" },
+                    self.protocol_version
+                )));
+            }
+        }
+        if self.relay_transactions.is_some() && self.protocol_version < RELAY_VERSION {
+            return Err(ser::Error::custom(format!(
+                "relay_transactions must not be set for protocol version {}",
+                self.protocol_version
+            )));
+        }
+
+        let length = if has_address_from { 8 } else { 4 } + self.relay_transactions.iter().len();
+        let mut tuple = serializer.serialize_tuple(length)?;
+        tuple.serialize_element(&self.protocol_version)?;
+        tuple.serialize_element(&self.local_node_services)?;
+        tuple.serialize_element(&self.unix_timestamp)?;
+        tuple.serialize_element(&self.node_ip_data)?;
+        if has_address_from {
+            tuple.serialize_element(&self.our_ip_data)?;
+            tuple.serialize_element(&self.nonce)?;
+            tuple.serialize_element(&self.client_name)?;
+            tuple.serialize_element(&self.node_starting_height)?;
+        }
+        if let Some(relay_transactions) = self.relay_transactions {
+            tuple.serialize_element(&relay_transactions)?;
         }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionMessageData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(VERSION_FIELD_COUNT, VersionMessageDataVisitor)
+    }
+}
 
-        let protocol_version =
-            slice_to_u32(&bytes[0..4]).ok_or(NetworkSerializationError::UnknownBytes)?;
-        let local_node_services =
-            slice_to_u64(&bytes[4..12]).ok_or(NetworkSerializationError::UnknownBytes)?;
-        let unix_timestamp =
-            slice_to_u64(&bytes[12..20]).ok_or(NetworkSerializationError::UnknownBytes)?;
-        let node_ip_data = IpData::from_network_bytes(&bytes[20..46])?;
-        let our_ip_data = IpData::from_network_bytes(&bytes[46..72])?;
-        let nonce = slice_to_u64(&bytes[72..80]).ok_or(NetworkSerializationError::UnknownBytes)?;
+struct VersionMessageDataVisitor;
 
-        let result = String::from_dogecoin_bytes(&bytes[80..bytes.len()])?;
-        let client_name = result.value;
+fn next_field<'de, T: Deserialize<'de>, A: SeqAccess<'de>>(
+    seq: &mut A,
+    index: usize,
+) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, &"version message payload"))
+}
+
+impl<'de> Visitor<'de> for VersionMessageDataVisitor {
+    type Value = VersionMessageData;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "version message payload")
+    }
 
-        let offset = 80 + result.bytes_read;
-        let node_starting_height = slice_to_u32(&bytes[offset..offset + 4])
-            .ok_or(NetworkSerializationError::UnknownBytes)?;
-        let relay_transactions = bytes[offset + 4] != 0;
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let protocol_version: u32 = next_field(&mut seq, 0)?;
+        let local_node_services = next_field(&mut seq, 1)?;
+        let unix_timestamp = next_field(&mut seq, 2)?;
+        let node_ip_data = next_field(&mut seq, 3)?;
+
+        let (our_ip_data, nonce, client_name, node_starting_height) =
+            if protocol_version >= ADDRESS_FROM_VERSION {
+                (
+                    Some(next_field(&mut seq, 4)?),
+                    Some(next_field(&mut seq, 5)?),
+                    Some(next_field(&mut seq, 6)?),
+                    Some(next_field(&mut seq, 7)?),
+                )
+            } else {
+                (None, None, None, None)
+            };
+
+        let relay_transactions = if protocol_version >= RELAY_VERSION {
+            seq.next_element::<Option<bool>>()?.flatten()
+        } else {
+            None
+        };
 
         Ok(VersionMessageData {
             protocol_version,
@@ -168,38 +372,33 @@ impl NetworkSerializable<VersionMessageData> for VersionMessageData {
             relay_transactions,
         })
     }
+}
 
-    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
-        let mut buffer = Vec::new();
-
-        let serialized_string_and_length_bytes_size =
-            calculate_size_of_serialized_string_and_length_bytes(self.client_name.len())?;
+impl NetworkSerializable<IpData> for IpData {
+    fn from_network_bytes(bytes: &[u8]) -> Result<IpData, NetworkSerializationError> {
+        serde_wire::from_bytes(bytes)
+    }
 
-        let message_size = DATA_SIZE_WITHOUT_CLIENT_NAME + serialized_string_and_length_bytes_size;
-        buffer.reserve(message_size);
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        serde_wire::to_bytes(self)
+    }
+}
 
-        buffer.extend_from_slice(&self.protocol_version.to_le_bytes());
-        buffer.extend_from_slice(&self.local_node_services.to_le_bytes());
-        buffer.extend_from_slice(&self.unix_timestamp.to_le_bytes());
-        buffer.extend_from_slice(&self.node_ip_data.to_network_bytes()?);
-        buffer.extend_from_slice(&self.our_ip_data.to_network_bytes()?);
-        buffer.extend_from_slice(&self.nonce.to_le_bytes());
-        buffer.extend_from_slice(self.client_name.to_dogecoin_bytes()?.as_ref());
-        buffer.extend_from_slice(&self.node_starting_height.to_le_bytes());
-        buffer.push(self.relay_transactions.into());
+impl NetworkSerializable<VersionMessageData> for VersionMessageData {
+    fn from_network_bytes(bytes: &[u8]) -> Result<VersionMessageData, NetworkSerializationError> {
+        serde_wire::from_bytes(bytes)
+    }
 
-        Ok(buffer)
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        serde_wire::to_bytes(self)
     }
 }
 
 impl NetworkSerializable<Version> for Version {
     fn from_network_bytes(bytes: &[u8]) -> Result<Version, NetworkSerializationError> {
-        if bytes.len() < HEADER_SIZE + DATA_SIZE_WITHOUT_CLIENT_NAME {
-            return Err(NetworkSerializationError::UnknownBytes);
-        }
-
-        let header = Header::from_network_bytes(&bytes[0..HEADER_SIZE])?;
-        let data = VersionMessageData::from_network_bytes(&bytes[HEADER_SIZE..bytes.len()])?;
+        let mut reader = Reader::new(bytes);
+        let header = Header::from_network_bytes(reader.read_bytes(HEADER_SIZE)?)?;
+        let data = serde_wire::from_reader(&mut reader)?;
 
         Ok(Version { header, data })
     }
@@ -231,55 +430,48 @@ mod tests {
 
     #[test]
     fn test_fill_version_message_data() {
-        let data = Version::new(NetworkType::Test, 0, "invalid ip", 0, 0, "");
-        assert!(data.is_err());
-        assert_eq!(data.unwrap_err(), IntegerParsingFailure);
-
         let data = Version::new(
-            NetworkType::Test,
+            &NetworkParams::TEST,
             1681155665,
-            "52.77.231.41",
-            44556,
+            "52.77.231.41:44556".parse().unwrap(),
             17898312933758525253,
             "/Shibetoshi:1.14.6/",
-        );
-        assert!(data.is_ok());
+        )
+        .unwrap();
         assert_eq!(
             VersionMessageData {
                 protocol_version: 70015,
-                local_node_services: 5,
+                local_node_services: ServiceFlags::from_bits(5),
                 unix_timestamp: 1681155665,
                 node_ip_data: IpData {
-                    node_services: 1,
-                    ip_address: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 52, 77, 231, 41],
+                    node_services: ServiceFlags::from_bits(1),
+                    ip_address: "52.77.231.41".parse().unwrap(),
                     port: 44556,
                 },
-                our_ip_data: IpData {
-                    node_services: 5,
-                    ip_address: [0; 16],
+                our_ip_data: Some(IpData {
+                    node_services: ServiceFlags::from_bits(5),
+                    ip_address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
                     port: 0,
-                },
-                nonce: 17898312933758525253,
-                client_name: "/Shibetoshi:1.14.6/".to_string(),
-                node_starting_height: 0,
-                relay_transactions: true,
+                }),
+                nonce: Some(17898312933758525253),
+                client_name: Some(b"/Shibetoshi:1.14.6/".to_vec()),
+                node_starting_height: Some(0),
+                relay_transactions: Some(true),
             },
-            data.unwrap().data
+            data.data
         );
     }
 
     #[test]
     fn test_build_version_message() {
         let data = Version::new(
-            NetworkType::Test,
+            &NetworkParams::TEST,
             1681155665,
-            "52.77.231.41",
-            44556,
+            "52.77.231.41:44556".parse().unwrap(),
             17898312933758525253,
             "/Shibetoshi:1.14.6/",
-        );
-        assert!(data.is_ok());
-        let data = data.unwrap();
+        )
+        .unwrap();
 
         let bytes = data.to_network_bytes();
         assert!(bytes.is_ok());
@@ -293,29 +485,234 @@ mod tests {
         let header = data.header;
         let data = data.data;
 
-        assert_eq!(header.network_type, NetworkType::Test);
-        assert_eq!(header.command, "version");
+        assert_eq!(header.magic, NetworkParams::TEST.magic);
+        assert_eq!(header.command.as_str(), "version");
         assert_eq!(header.message_size, 105);
         assert_eq!(header.hash, [0xa2, 0xbb, 0x58, 0x1c]);
 
         assert_eq!(data.protocol_version, 70015);
-        assert_eq!(data.local_node_services, 5);
+        assert_eq!(data.local_node_services, ServiceFlags::from_bits(5));
         assert_eq!(data.unix_timestamp, 1681155665);
 
-        assert_eq!(data.node_ip_data.node_services, 1);
+        assert_eq!(data.node_ip_data.node_services, ServiceFlags::from_bits(1));
         assert_eq!(
             data.node_ip_data.ip_address,
-            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 52, 77, 231, 41]
+            "52.77.231.41".parse::<IpAddr>().unwrap()
         );
         assert_eq!(data.node_ip_data.port, 44556);
 
-        assert_eq!(data.our_ip_data.node_services, 5);
-        assert_eq!(data.our_ip_data.ip_address, [0; 16]);
-        assert_eq!(data.our_ip_data.port, 0);
+        let our_ip_data = data.our_ip_data.as_ref().unwrap();
+        assert_eq!(our_ip_data.node_services, ServiceFlags::from_bits(5));
+        assert_eq!(our_ip_data.ip_address, IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        assert_eq!(our_ip_data.port, 0);
+
+        assert_eq!(data.nonce, Some(17898312933758525253));
+        assert_eq!(
+            data.client_name.as_deref(),
+            Some(b"/Shibetoshi:1.14.6/".as_slice())
+        );
+        let user_agent = data.user_agent().unwrap().unwrap();
+        assert_eq!("Shibetoshi", user_agent.components[0].name);
+        assert_eq!(data.node_starting_height, Some(0));
+        assert_eq!(data.relay_transactions, Some(true));
+    }
+
+    #[test]
+    fn test_parse_truncated_version_message() {
+        let payload = &FULL_VERSION_MESSAGE[HEADER_SIZE..];
+        for length in 0..payload.len() - 1 {
+            assert!(VersionMessageData::from_network_bytes(&payload[..length]).is_err());
+        }
+
+        // user agent length claims more bytes than the payload carries
+        let mut payload = payload[..81].to_vec();
+        payload[80] = 0xFE;
+        assert!(matches!(
+            VersionMessageData::from_network_bytes(&payload),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 80,
+                needed: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_old_version_messages() {
+        let payload = &FULL_VERSION_MESSAGE[HEADER_SIZE..];
+
+        // 70015 peer leaving out the optional relay flag
+        let data = VersionMessageData::from_network_bytes(&payload[..payload.len() - 1]).unwrap();
+        assert_eq!(Some(0), data.node_starting_height);
+        assert_eq!(None, data.relay_transactions);
+        assert_eq!(
+            &payload[..payload.len() - 1],
+            data.to_network_bytes().unwrap().as_slice()
+        );
+
+        // 60002 predates BIP37, relay is never read
+        let mut bytes = payload.to_vec();
+        bytes[0..4].copy_from_slice(&60002u32.to_le_bytes());
+        let data = VersionMessageData::from_network_bytes(&bytes).unwrap();
+        assert_eq!(Some(0), data.node_starting_height);
+        assert_eq!(None, data.relay_transactions);
+
+        // 105 sends nothing past addr_recv
+        let mut bytes = payload[..46].to_vec();
+        bytes[0..4].copy_from_slice(&105u32.to_le_bytes());
+        let data = VersionMessageData::from_network_bytes(&bytes).unwrap();
+        assert_eq!(105, data.protocol_version);
+        assert_eq!(44556, data.node_ip_data.port);
+        assert_eq!(None, data.our_ip_data);
+        assert_eq!(None, data.nonce);
+        assert_eq!(None, data.client_name);
+        assert_eq!(None, data.node_starting_height);
+        assert_eq!(bytes, data.to_network_bytes().unwrap());
+
+        // 106 fields are mandatory from then on
+        bytes[0..4].copy_from_slice(&106u32.to_le_bytes());
+        assert!(matches!(
+            VersionMessageData::from_network_bytes(&bytes),
+            Err(NetworkSerializationError::BufferTooShort { offset: 46, .. })
+        ));
+    }
+
+    #[test]
+    fn test_serialize_fields_not_matching_version() {
+        let payload = &FULL_VERSION_MESSAGE[HEADER_SIZE..];
+        let data = VersionMessageData::from_network_bytes(payload).unwrap();
+
+        // Would write the nonce where peers read addr_from
+        let missing = VersionMessageData {
+            our_ip_data: None,
+            ..data.clone()
+        };
+        assert!(matches!(
+            missing.to_network_bytes(),
+            Err(NetworkSerializationError::Custom(message))
+                if message == "our_ip_data must be set for protocol version 70015"
+        ));
+
+        let too_old = VersionMessageData {
+            protocol_version: 105,
+            relay_transactions: None,
+            ..data.clone()
+        };
+        assert!(matches!(
+            too_old.to_network_bytes(),
+            Err(NetworkSerializationError::Custom(message))
+                if message == "our_ip_data must not be set for protocol version 105"
+        ));
+
+        let relay_before_bip37 = VersionMessageData {
+            protocol_version: 60002,
+            ..data
+        };
+        assert!(matches!(
+            relay_before_bip37.to_network_bytes(),
+            Err(NetworkSerializationError::Custom(message))
+                if message == "relay_transactions must not be set for protocol version 60002"
+        ));
+    }
+
+    #[test]
+    fn test_version_builder() {
+        let advertised_address = IpData {
+            node_services: ServiceFlags::from_bits(1),
+            ip_address: "203.0.113.7".parse().unwrap(),
+            port: 22556,
+        };
+        let data = VersionBuilder::new()
+            .protocol_version(70003)
+            .services(ServiceFlags::NODE_NETWORK)
+            .advertised_address(advertised_address.clone())
+            .start_height(4_419_828)
+            .relay_transactions(false)
+            .user_agent("/crawler:0.1/")
+            .nonce_source(NonceSource::Fixed(42))
+            .unix_timestamp(1681155665)
+            .build_for_peer("52.77.231.41:44556".parse().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            VersionMessageData {
+                protocol_version: 70003,
+                local_node_services: ServiceFlags::from_bits(1),
+                unix_timestamp: 1681155665,
+                node_ip_data: IpData {
+                    node_services: ServiceFlags::from_bits(1),
+                    ip_address: "52.77.231.41".parse().unwrap(),
+                    port: 44556,
+                },
+                our_ip_data: Some(advertised_address),
+                nonce: Some(42),
+                client_name: Some(b"/crawler:0.1/".to_vec()),
+                node_starting_height: Some(4_419_828),
+                relay_transactions: Some(false),
+            },
+            data
+        );
+    }
+
+    #[test]
+    fn test_version_builder_validation() {
+        let result = VersionBuilder::new()
+            .user_agent(&"x".repeat(MAX_USER_AGENT_LENGTH + 1))
+            .build();
+        assert!(matches!(
+            result,
+            Err(VersionBuildError::UserAgentTooLong(257))
+        ));
+
+        let result = VersionBuilder::new()
+            .user_agent(&"x".repeat(MAX_USER_AGENT_LENGTH))
+            .build();
+        assert!(result.is_ok());
+
+        let result = VersionBuilder::new().user_agent("/evil\n:1.0/").build();
+        assert!(matches!(
+            result,
+            Err(VersionBuildError::InvalidUserAgent(
+                UserAgentError::NonPrintable {
+                    offset: 5,
+                    byte: b'\n'
+                }
+            ))
+        ));
+
+        let result = VersionBuilder::new().protocol_version(106).build();
+        assert!(matches!(
+            result,
+            Err(VersionBuildError::ProtocolVersionTooLow(106))
+        ));
+    }
 
-        assert_eq!(data.nonce, 17898312933758525253);
-        assert_eq!(data.client_name, "/Shibetoshi:1.14.6/");
-        assert_eq!(data.node_starting_height, 0);
-        assert_eq!(data.relay_transactions, true);
+    #[test]
+    fn test_ip_data_ipv6() {
+        let bytes = [
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x57, 0x1c,
+        ];
+        let ip_data = IpData::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            IpData {
+                node_services: ServiceFlags::from_bits(1),
+                ip_address: "2001:db8::1".parse().unwrap(),
+                port: 22300,
+            },
+            ip_data
+        );
+        assert_eq!(&bytes, ip_data.to_network_bytes().unwrap().as_slice());
+
+        let ip_data = IpData {
+            node_services: ServiceFlags::from_bits(0),
+            ip_address: "10.0.0.1".parse().unwrap(),
+            port: 22556,
+        };
+        let bytes = ip_data.to_network_bytes().unwrap();
+        assert_eq!(
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 10, 0, 0, 1],
+            &bytes[8..24]
+        );
+        assert_eq!(ip_data, IpData::from_network_bytes(&bytes).unwrap());
     }
 }
@@ -1,73 +1,124 @@
+pub mod address_manager;
+#[cfg(feature = "async")]
+pub mod async_node_connection_agent;
+pub mod block_header;
+pub mod connection_config;
 pub mod errors;
+pub mod frame;
+pub mod handshake;
+pub mod hash256;
 pub mod header;
+pub mod header_chain;
 pub mod messages;
+pub mod network_params;
 pub mod node_connection_agent;
+pub mod node_listener;
+pub mod pow;
+pub mod resolver;
+pub mod serde_wire;
 pub mod serializer;
+pub mod service_flags;
+pub mod user_agent;
 
 use bitcoin_hashes::Hash;
 
-use errors::*;
+pub use dogecoin_handshaker_derive::NetworkSerializable;
+pub use network_params::NetworkParams;
 
-pub type IpAddress = [u8; 16];
+use errors::*;
 
 pub trait NetworkSerializable<T: Sized> {
     fn from_network_bytes(bytes: &[u8]) -> Result<T, NetworkSerializationError>;
     fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError>;
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum NetworkType {
-    Main,
-    Test,
-    RegressionTest,
+fn calculate_message_hash(message: &[u8]) -> [u8; 4] {
+    let hash = bitcoin_hashes::sha256d::Hash::hash(message);
+    [hash[0], hash[1], hash[2], hash[3]]
 }
 
-impl NetworkSerializable<NetworkType> for NetworkType {
-    fn from_network_bytes(bytes: &[u8]) -> Result<NetworkType, NetworkSerializationError> {
-        if bytes == [0xC0, 0xC0, 0xC0, 0xC0] {
-            Ok(NetworkType::Main)
-        } else if bytes == [0xFC, 0xC1, 0xB7, 0xDC] {
-            Ok(NetworkType::Test)
-        } else if bytes == [0xFA, 0xBF, 0xB5, 0xDA] {
-            Ok(NetworkType::RegressionTest)
-        } else {
-            Err(NetworkSerializationError::UnknownBytes)
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::block_header::{AuxPow, BlockHeader, MERGED_MINING_HEADER};
+    use super::calculate_message_hash;
+    use super::network_params::hash_from_hex;
+    use super::{hash256::Hash256, NetworkParams};
+
+    pub const MAIN_GENESIS_HEADER: BlockHeader = BlockHeader {
+        version: 1,
+        prev_block: Hash256::ZERO,
+        merkle_root: Hash256::from_hex(
+            "5b2a3f53f605d62c53e62932dac6925e3d74afa5a4b459745c36d42d0ed26a69",
+        ),
+        time: 1386325540,
+        bits: 0x1e0ffff0,
+        nonce: 99943,
+    };
+
+    // Next to every hash meets this target, so tests can mine chains in no time.
+    // Each header is worth one unit of work, three of them make the minimum
+    pub const EASY_NETWORK: NetworkParams = NetworkParams {
+        pow_limit_bits: 0x2100ffff,
+        min_chain_work: hash_from_hex(
+            "0000000000000000000000000000000000000000000000000000000000000003",
+        ),
+        ..NetworkParams::MAIN
+    };
 
-    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
-        match self {
-            NetworkType::Main => Ok(vec![0xC0, 0xC0, 0xC0, 0xC0]),
-            NetworkType::Test => Ok(vec![0xFC, 0xC1, 0xB7, 0xDC]),
-            NetworkType::RegressionTest => Ok(vec![0xFA, 0xBF, 0xB5, 0xDA]),
+    pub fn mine_child(prev_block: Hash256, time: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            prev_block,
+            time,
+            bits: EASY_NETWORK.pow_limit_bits,
+            ..MAIN_GENESIS_HEADER
+        };
+        while header.check_proof_of_work(header.bits).is_err() {
+            header.nonce += 1;
         }
+
+        header
     }
-}
 
-fn calculate_message_hash(message: &[u8]) -> [u8; 4] {
-    let hash = bitcoin_hashes::sha256d::Hash::hash(message);
-    [hash[0], hash[1], hash[2], hash[3]]
-}
+    // Parent chain block whose coinbase commits to block alone, mined on the easy target
+    pub fn merge_mine(block: &BlockHeader) -> AuxPow {
+        let mut script = MERGED_MINING_HEADER.to_vec();
+        script.extend(block.hash().as_bytes().iter().rev());
+        script.extend(1u32.to_le_bytes());
+        script.extend(0u32.to_le_bytes());
 
-fn string_to_ip(ip_address: &str) -> Option<[u8; 4]> {
-    let splits = ip_address.split('.').collect::<Vec<&str>>();
-    if splits.len() != 4 {
-        return None;
+        merge_mine_with_script(script, block.time)
     }
 
-    let octets = (
-        splits[0].parse::<u8>().ok()?,
-        splits[1].parse::<u8>().ok()?,
-        splits[2].parse::<u8>().ok()?,
-        splits[3].parse::<u8>().ok()?,
-    );
+    pub fn merge_mine_with_script(script: Vec<u8>, time: u32) -> AuxPow {
+        // version, one input spending nothing with the script above, no outputs, locktime
+        let mut coinbase_tx = vec![0x01, 0x00, 0x00, 0x00, 0x01];
+        coinbase_tx.extend([0x00; 32]);
+        coinbase_tx.extend([0xFF; 4]);
+        coinbase_tx.push(script.len() as u8);
+        coinbase_tx.extend(script);
+        coinbase_tx.extend([0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00]);
 
-    Some([octets.0, octets.1, octets.2, octets.3])
-}
+        let mut aux_pow = AuxPow {
+            coinbase_tx,
+            parent_block_hash: Hash256::ZERO,
+            coinbase_branch: Vec::new(),
+            coinbase_index: 0,
+            chain_branch: Vec::new(),
+            chain_index: 0,
+            parent_header: mine_child(Hash256::ZERO, time),
+        };
+        aux_pow.parent_header.merkle_root = aux_pow.coinbase_hash();
+        while aux_pow
+            .parent_header
+            .check_proof_of_work(EASY_NETWORK.pow_limit_bits)
+            .is_err()
+        {
+            aux_pow.parent_header.nonce += 1;
+        }
+        aux_pow.parent_block_hash = aux_pow.parent_header.hash();
 
-#[cfg(test)]
-mod tests {
-    use super::calculate_message_hash;
+        aux_pow
+    }
 
     pub const VERSION_MESSAGE: [u8; 105] = [
         0x7f, 0x11, 0x01, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x51, 0x66, 0x34,
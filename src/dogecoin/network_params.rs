@@ -0,0 +1,141 @@
+use super::messages::version::PROTOCOL_VERSION;
+
+// Everything that tells one Dogecoin-derived network apart from another, see chainparams.cpp in Core
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkParams {
+    pub name: &'static str,
+    pub magic: [u8; 4],
+    pub default_port: u16,
+    pub dns_seeds: &'static [&'static str],
+    pub protocol_version: u32,
+    // Internal byte order, reversed compared to how block explorers print it
+    pub genesis_hash: [u8; 32],
+    // Easiest allowed proof of work target, in compact form like a header's bits
+    pub pow_limit_bits: u32,
+    // Merge mined headers must carry this in the upper half of their version
+    pub aux_pow_chain_id: i32,
+    // A synced chain with less total work is not trusted, internal byte order like genesis_hash.
+    // Kept well below the real chains so it never turns away an honest peer
+    pub min_chain_work: [u8; 32],
+}
+
+impl NetworkParams {
+    pub const MAIN: NetworkParams = NetworkParams {
+        name: "main",
+        magic: [0xC0, 0xC0, 0xC0, 0xC0],
+        default_port: 22556,
+        dns_seeds: &["seed.multidoge.org", "seed2.multidoge.org"],
+        protocol_version: PROTOCOL_VERSION,
+        genesis_hash: hash_from_hex(
+            "1a91e3dace36e2be3bf030a65679fe821aa1d6ef92e7c9902eb318182c355691",
+        ),
+        pow_limit_bits: 0x1e0fffff,
+        aux_pow_chain_id: 0x0062,
+        min_chain_work: hash_from_hex(
+            "0000000000000000000000000000000000000000000001000000000000000000",
+        ),
+    };
+
+    pub const TEST: NetworkParams = NetworkParams {
+        name: "test",
+        magic: [0xFC, 0xC1, 0xB7, 0xDC],
+        default_port: 44556,
+        dns_seeds: &["testseed.jrn.me.uk"],
+        protocol_version: PROTOCOL_VERSION,
+        genesis_hash: hash_from_hex(
+            "bb0a78264637406b6360aad926284d544d7049f45189db5664f3c4d07350559e",
+        ),
+        pow_limit_bits: 0x1e0fffff,
+        aux_pow_chain_id: 0x0062,
+        min_chain_work: hash_from_hex(
+            "0000000000000000000000000000000000000000000000000000010000000000",
+        ),
+    };
+
+    pub const REGTEST: NetworkParams = NetworkParams {
+        name: "regtest",
+        magic: [0xFA, 0xBF, 0xB5, 0xDA],
+        default_port: 18444,
+        dns_seeds: &[],
+        protocol_version: PROTOCOL_VERSION,
+        genesis_hash: hash_from_hex(
+            "3d2160a3b5dc4a9d62e7e66a295f70313ac808440ef7400d6c0772171ce973a5",
+        ),
+        pow_limit_bits: 0x207fffff,
+        aux_pow_chain_id: 0x0062,
+        min_chain_work: [0; 32],
+    };
+
+    pub const BUILT_IN: [&'static NetworkParams; 3] = [
+        &NetworkParams::MAIN,
+        &NetworkParams::TEST,
+        &NetworkParams::REGTEST,
+    ];
+
+    pub fn from_magic(magic: &[u8]) -> Option<&'static NetworkParams> {
+        NetworkParams::BUILT_IN
+            .into_iter()
+            .find(|network| network.magic == magic)
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static NetworkParams> {
+        NetworkParams::BUILT_IN
+            .into_iter()
+            .find(|network| network.name == name)
+    }
+}
+
+// Parses a hash as printed by Core, for use in consts
+pub const fn hash_from_hex(hex: &str) -> [u8; 32] {
+    const fn nibble(character: u8) -> u8 {
+        match character {
+            b'0'..=b'9' => character - b'0',
+            b'a'..=b'f' => character - b'a' + 10,
+            b'A'..=b'F' => character - b'A' + 10,
+            _ => panic!("invalid hex character"),
+        }
+    }
+
+    let hex = hex.as_bytes();
+    assert!(hex.len() == 64);
+
+    let mut hash = [0; 32];
+    let mut index = 0;
+    while index < 32 {
+        hash[31 - index] = nibble(hex[index * 2]) << 4 | nibble(hex[index * 2 + 1]);
+        index += 1;
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_magic() {
+        assert_eq!(
+            Some(&NetworkParams::MAIN),
+            NetworkParams::from_magic(&[0xC0, 0xC0, 0xC0, 0xC0])
+        );
+        assert_eq!(
+            Some(&NetworkParams::TEST),
+            NetworkParams::from_magic(&[0xFC, 0xC1, 0xB7, 0xDC])
+        );
+        assert_eq!(
+            Some(&NetworkParams::REGTEST),
+            NetworkParams::from_magic(&[0xFA, 0xBF, 0xB5, 0xDA])
+        );
+        assert_eq!(None, NetworkParams::from_magic(&[0xFB, 0xC0, 0xB6, 0xDB]));
+        assert_eq!(Some(&NetworkParams::TEST), NetworkParams::from_name("test"));
+    }
+
+    #[test]
+    fn test_hash_from_hex() {
+        let hash = NetworkParams::MAIN.genesis_hash;
+        assert_eq!([0x91, 0x56, 0x35, 0x2c], hash[0..4]);
+        assert_eq!([0xce, 0xda, 0xe3, 0x91, 0x1a], hash[27..32]);
+    }
+}
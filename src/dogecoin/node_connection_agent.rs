@@ -1,121 +1,1219 @@
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use std::io::{Read, Write};
-use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
-use std::str::FromStr;
-use std::time::{SystemTime, SystemTimeError};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use super::*;
+use address_manager::AddressManager;
+use connection_config::ConnectionConfig;
 use errors::*;
-use header::Header;
-use messages::{verack::Verack, version::Version};
+use frame::{decode_frame, RECEIVE_CHUNK_SIZE};
+use handshake::{Handshake, HandshakeAction, HandshakeOutcome};
+use hash256::Hash256;
+use header_chain::HeaderChain;
+use messages::addr::{TimestampedIpData, MAX_ADDR_TO_SEND};
+use messages::getheaders::GetHeaders;
+use messages::{ping::Ping, pong::Pong, version::VersionMessageData, Message};
+use resolver::{DnsResolver, Resolver};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeepaliveConfig {
+    pub ping_interval: Duration,
+    pub max_missed_pongs: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        // Dogecoin Core pings every 2 minutes
+        Self {
+            ping_interval: Duration::from_secs(2 * 60),
+            max_missed_pongs: 3,
+        }
+    }
+}
+
+// Aborts blocking operations of the agent it was taken from, usable from other threads
+#[derive(Clone)]
+pub struct CancellationHandle {
+    cancelled: Arc<AtomicBool>,
+    stream: Arc<TcpStream>,
+}
+
+impl CancellationHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
 
 pub struct NodeConnectionAgent {
-    ip: String,
-    network_type: NetworkType,
-    port: u16,
-    random_number_generator: ThreadRng,
+    address_manager: Option<Arc<Mutex<AddressManager>>>,
+    cancelled: Arc<AtomicBool>,
+    config: ConnectionConfig,
+    latency: Option<Duration>,
+    missed_pongs: u32,
+    network: NetworkParams,
+    peer_address: SocketAddr,
+    pending_messages: VecDeque<Message>,
+    pending_ping: Option<(u64, Instant)>,
+    // Not ThreadRng, agents get moved to their own thread
+    random_number_generator: StdRng,
+    receive_buffer: Vec<u8>,
     stream: TcpStream,
 }
 
 impl NodeConnectionAgent {
     pub fn new(
-        network_type: NetworkType,
-        ip: &str,
+        network: NetworkParams,
+        host: &str,
         port: u16,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let address = SocketAddrV4::new(Ipv4Addr::from_str(ip)?, port);
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::new_with_config(network, host, port, config)
+    }
+
+    pub fn new_with_config(
+        network: NetworkParams,
+        host: &str,
+        port: u16,
+        config: ConnectionConfig,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let mut last_error = None;
+        for address in (host, port).to_socket_addrs()? {
+            match Self::connect_with_config(network.clone(), address, config.clone()) {
+                Ok(agent) => return Ok(agent),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+            .into()
+        }))
+    }
+
+    pub fn connect(
+        network: NetworkParams,
+        address: SocketAddr,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::connect_with_config(network, address, config)
+    }
+
+    pub fn connect_with_config(
+        network: NetworkParams,
+        address: SocketAddr,
+        config: ConnectionConfig,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let stream = match config.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        }
+        .map_err(|e| NodeConnectionAgentError::from_io_error(e, TimeoutPhase::Connect))?;
+
+        Self::from_stream_with_config(network, stream, config)
+    }
+
+    pub fn connect_to_network(
+        network: NetworkParams,
+    ) -> Result<(Self, HandshakeOutcome), NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::connect_to_network_with_config(network, &DnsResolver, config)
+    }
+
+    // Tries every address behind the DNS seeds in order, first completed handshake wins
+    pub fn connect_to_network_with_config<R: Resolver>(
+        network: NetworkParams,
+        resolver: &R,
+        config: ConnectionConfig,
+    ) -> Result<(Self, HandshakeOutcome), NodeConnectionAgentError> {
+        let mut last_error = None;
+        for seed in network.dns_seeds {
+            let addresses = match resolver.resolve(seed, network.default_port) {
+                Ok(addresses) => addresses,
+                Err(e) => {
+                    last_error = Some(e.into());
+                    continue;
+                }
+            };
+
+            for address in addresses {
+                let result = Self::connect_with_config(network.clone(), address, config.clone())
+                    .and_then(|mut agent| {
+                        let outcome = agent.handshake()?;
+                        Ok((agent, outcome))
+                    });
+                match result {
+                    Ok(result) => return Ok(result),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(NodeConnectionAgentError::NoCandidatePeers))
+    }
+
+    pub fn from_stream(
+        network: NetworkParams,
+        stream: TcpStream,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let config = ConnectionConfig::for_network(&network);
+
+        Self::from_stream_with_config(network, stream, config)
+    }
+
+    pub fn from_stream_with_config(
+        network: NetworkParams,
+        stream: TcpStream,
+        config: ConnectionConfig,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        let peer_address = stream.peer_addr()?;
+        stream.set_read_timeout(config.read_timeout)?;
+        stream.set_write_timeout(config.write_timeout)?;
 
         Ok(Self {
-            ip: ip.to_string(),
-            network_type,
-            port,
-            random_number_generator: rand::thread_rng(),
-            stream: TcpStream::connect(address)?,
+            address_manager: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            config,
+            latency: None,
+            missed_pongs: 0,
+            network,
+            peer_address,
+            pending_messages: VecDeque::new(),
+            pending_ping: None,
+            random_number_generator: StdRng::from_entropy(),
+            receive_buffer: Vec::new(),
+            stream,
         })
     }
-    
-    pub fn read_version_ack(&mut self) -> Result<(), NodeConnectionAgentError> {
-        let mut header_buffer: [u8; 24] = [0; 24];
-        let read_size = self.stream.read(&mut header_buffer)?;
-        if read_size != header_buffer.len() {
-            return Err(NodeConnectionAgentError::IncorrectNumberOfBytesReceived);
-        }
-        
-        let verack_message = Verack::new(self.network_type.clone());
-        let verack_bytes = verack_message.to_network_bytes()?;
-    
-        if header_buffer != verack_bytes.as_slice() {
-            return Err(NodeConnectionAgentError::IncorrectResponse);
-        }
-        
+
+    pub fn cancellation_handle(&self) -> Result<CancellationHandle, NodeConnectionAgentError> {
+        Ok(CancellationHandle {
+            cancelled: self.cancelled.clone(),
+            stream: Arc::new(self.stream.try_clone()?),
+        })
+    }
+
+    pub fn peer_address(&self) -> SocketAddr {
+        self.peer_address
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    // Handshakes and collected addresses get recorded in the shared manager from now on
+    pub fn set_address_manager(&mut self, address_manager: Arc<Mutex<AddressManager>>) {
+        self.address_manager = Some(address_manager);
+    }
+
+    pub fn keep_alive<F>(
+        &mut self,
+        config: &KeepaliveConfig,
+        mut on_message: F,
+    ) -> Result<(), NodeConnectionAgentError>
+    where
+        F: FnMut(Message) -> ControlFlow<()>,
+    {
+        let result = self.run_keepalive_loop(config, &mut on_message);
+        self.stream.set_read_timeout(self.config.read_timeout)?;
+
+        if let Err(NodeConnectionAgentError::PingTimeout(_)) = result {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+
+        result
+    }
+
+    pub fn send_ping(&mut self) -> Result<u64, NodeConnectionAgentError> {
+        let nonce = self.random_number_generator.gen();
+        self.write_message(&Message::Ping(Ping { nonce }))?;
+        self.pending_ping = Some((nonce, Instant::now()));
+
+        Ok(nonce)
+    }
+
+    pub fn handshake(&mut self) -> Result<HandshakeOutcome, NodeConnectionAgentError> {
+        let peer_address = self.peer_address;
+        self.update_address_manager(|manager| manager.attempt(&peer_address));
+
+        let handshake = Handshake::new(self.config.version.get_protocol_version());
+        self.send_version()?;
+        let outcome = self.run_handshake(handshake)?;
+
+        let services = outcome.peer_version.local_node_services;
+        self.update_address_manager(|manager| manager.good(&peer_address, services));
+
+        Ok(outcome)
+    }
+
+    // Sends getaddr and gathers addr answers until the window closes or the cap is reached.
+    // Messages that fail to decode are skipped, and when the connection fails midway what was
+    // gathered so far is still returned, the failure shows up again on the next read.
+    pub fn request_addresses(
+        &mut self,
+        window: Duration,
+    ) -> Result<Vec<TimestampedIpData>, NodeConnectionAgentError> {
+        self.write_message(&Message::GetAddr)?;
+
+        let deadline = Instant::now() + window;
+        let mut addresses = Vec::new();
+        let mut other_messages = Vec::new();
+        while addresses.len() < MAX_ADDR_TO_SEND {
+            match self.read_message_before(deadline, TimeoutPhase::Read) {
+                Ok(Message::Addr(addr)) => addresses.extend(addr.addresses),
+                Ok(Message::Ping(ping)) => {
+                    self.write_message(&Message::Pong(Pong { nonce: ping.nonce }))?
+                }
+                Ok(message) => other_messages.push(message),
+                Err(NodeConnectionAgentError::Timeout { .. }) if Instant::now() >= deadline => {
+                    break
+                }
+                // The frame was already consumed, the stream is still in sync
+                Err(NodeConnectionAgentError::NetworkSerializationFailure(_))
+                | Err(NodeConnectionAgentError::ChecksumMismatch { .. }) => {}
+                Err(e) if addresses.is_empty() && other_messages.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+        addresses.truncate(MAX_ADDR_TO_SEND);
+
+        let source = self.peer_address.ip();
+        self.update_address_manager(|manager| {
+            for address in &addresses {
+                manager.add(address, source);
+            }
+        });
+
+        // Anything else that arrived in the meantime is still up for read_message
+        self.pending_messages.extend(other_messages);
+
+        Ok(addresses)
+    }
+
+    // Extends the chain with getheaders rounds until it reaches target_height, which is
+    // usually the peer's node_starting_height, or until the peer has nothing more to give.
+    // A peer trickling other messages can't stretch it past timeout, headers connected
+    // before that are kept. A peer that runs out of headers before the chain has the
+    // network's minimum work is an error, reaching target_height first is not, so check
+    // HeaderChain::check_minimum_work before trusting a tip synced to a lower height.
+    pub fn sync_headers(
+        &mut self,
+        chain: &mut HeaderChain,
+        target_height: u32,
+        timeout: Duration,
+    ) -> Result<(), NodeConnectionAgentError> {
+        let deadline = Instant::now() + timeout;
+        let mut other_messages = Vec::new();
+        let result = self.run_header_sync(chain, target_height, deadline, &mut other_messages);
+
+        // Anything else that arrived in the meantime is still up for read_message
+        self.pending_messages.extend(other_messages);
+
+        result
+    }
+
+    pub fn accept_handshake(&mut self) -> Result<HandshakeOutcome, NodeConnectionAgentError> {
+        self.run_handshake(Handshake::new_responder(
+            self.config.version.get_protocol_version(),
+        ))
+    }
+
+    pub fn read_message(&mut self) -> Result<Message, NodeConnectionAgentError> {
+        if let Some(message) = self.pending_messages.pop_front() {
+            return Ok(message);
+        }
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(NodeConnectionAgentError::Cancelled);
+        }
+
+        loop {
+            if let Some(message) = decode_frame(
+                &mut self.receive_buffer,
+                &self.network,
+                self.config.max_message_size,
+                self.config.verify_checksums,
+            )? {
+                return Ok(message);
+            }
+            self.receive_more()?;
+        }
+    }
+
+    pub fn write_message(&mut self, message: &Message) -> Result<(), NodeConnectionAgentError> {
+        let bytes = message.to_network_bytes(&self.network)?;
+        self.stream
+            .write_all(bytes.as_slice())
+            .map_err(|e| self.stream_error(e, TimeoutPhase::Write))?;
+
         Ok(())
     }
 
-    pub fn receive_version(&mut self) -> Result<Version, NodeConnectionAgentError> {
-        let mut header_buffer: [u8; 24] = [0; 24];
-        let read_size = self.stream.read(&mut header_buffer)?;
-        if read_size != header_buffer.len() {
-            return Err(NodeConnectionAgentError::IncorrectNumberOfBytesReceived);
+    pub fn read_version_ack(&mut self) -> Result<(), NodeConnectionAgentError> {
+        match self.read_message()? {
+            Message::Verack => Ok(()),
+            message => Err(NodeConnectionAgentError::UnexpectedCommand(
+                "verack".to_string(),
+                message.command().to_string(),
+            )),
         }
+    }
 
-        let header = Header::from_network_bytes(&header_buffer)?;
-        if header.command != "version" {
-            return Err(NodeConnectionAgentError::UnexpectedCommand(
+    pub fn receive_version(&mut self) -> Result<VersionMessageData, NodeConnectionAgentError> {
+        match self.read_message()? {
+            Message::Version(data) => Ok(data),
+            message => Err(NodeConnectionAgentError::UnexpectedCommand(
                 "version".to_string(),
-                header.command,
-            ));
+                message.command().to_string(),
+            )),
         }
+    }
+
+    pub fn send_version_ack(&mut self) -> Result<(), NodeConnectionAgentError> {
+        self.write_message(&Message::Verack)
+    }
+
+    pub fn send_version(&mut self) -> Result<(), NodeConnectionAgentError> {
+        let data = self.config.version.build_for_peer(self.peer_address)?;
+
+        self.write_message(&Message::Version(data))
+    }
+
+    fn run_handshake(
+        &mut self,
+        mut handshake: Handshake,
+    ) -> Result<HandshakeOutcome, NodeConnectionAgentError> {
+        let deadline = self
+            .config
+            .handshake_timeout
+            .map(|timeout| Instant::now() + timeout);
 
-        let mut data_buffer: Vec<u8> = Vec::new();
-        data_buffer.resize(header.message_size, 0);
-        let read_size = self.stream.read(data_buffer.as_mut_slice())?;
-        if read_size != header.message_size {
-            return Err(NodeConnectionAgentError::IncorrectNumberOfBytesReceived);
+        while !handshake.is_complete() {
+            let message = match deadline {
+                Some(deadline) => self.read_message_before(deadline, TimeoutPhase::Handshake)?,
+                None => self.read_message()?,
+            };
+            match handshake.on_message(message)? {
+                HandshakeAction::None => {}
+                HandshakeAction::SendPong(nonce) => {
+                    self.write_message(&Message::Pong(Pong { nonce }))?
+                }
+                HandshakeAction::SendVerack => self.send_version_ack()?,
+                HandshakeAction::SendVersionAndVerack => {
+                    self.send_version()?;
+                    self.send_version_ack()?
+                }
+            }
         }
 
-        let mut version_bytes = Vec::new();
-        version_bytes.extend(header_buffer);
-        version_bytes.extend(data_buffer);
+        let (outcome, buffered_messages) = handshake.finish()?;
+        self.pending_messages.extend(buffered_messages);
 
-        Ok(Version::from_network_bytes(version_bytes.as_slice())?)
+        Ok(outcome)
     }
-    
-    pub fn send_version_ack(&mut self) -> Result<(), NodeConnectionAgentError> {
-        let verack_message = Verack::new(self.network_type.clone());
-        let verack_bytes = verack_message.to_network_bytes()?;
-        
-        let write_size = self.stream.write(verack_bytes.as_slice())?;
-        if write_size != verack_bytes.len() {
-            return Err(NodeConnectionAgentError::IncorrectNumberOfBytesSent);
-        }
-        
+
+    fn run_header_sync(
+        &mut self,
+        chain: &mut HeaderChain,
+        target_height: u32,
+        deadline: Instant,
+        other_messages: &mut Vec<Message>,
+    ) -> Result<(), NodeConnectionAgentError> {
+        while chain.height() < target_height {
+            self.write_message(&Message::GetHeaders(GetHeaders {
+                version: self.config.version.get_protocol_version(),
+                locator: chain.locator(),
+                hash_stop: Hash256::ZERO,
+            }))?;
+
+            let headers = loop {
+                match self.read_message_before(deadline, TimeoutPhase::HeaderSync)? {
+                    Message::Headers(headers) => break headers,
+                    Message::Ping(ping) => {
+                        self.write_message(&Message::Pong(Pong { nonce: ping.nonce }))?
+                    }
+                    message => other_messages.push(message),
+                }
+            };
+            if headers.headers.is_empty() {
+                chain.check_minimum_work()?;
+                break;
+            }
+            for entry in headers.headers {
+                chain.connect(entry.header, entry.aux_pow.as_ref())?;
+            }
+        }
+
         Ok(())
     }
 
-    pub fn send_version(&mut self) -> Result<(), NodeConnectionAgentError> {
-        const CLIENT_NAME: &str = "/Shibetoshi:1.14.6/";
-        let version_message = Version::new(
-            self.network_type.clone(),
-            Self::get_unix_timestamp()?,
-            self.ip.as_str(),
-            self.port,
-            self.random_number_generator.gen(),
-            CLIENT_NAME,
-        )?;
-        let version_message_bytes = version_message.to_network_bytes()?;
-
-        let write_result = self.stream.write(version_message_bytes.as_slice())?;
-        if write_result != version_message_bytes.len() {
-            return Err(NodeConnectionAgentError::IncorrectNumberOfBytesSent);
+    fn read_message_before(
+        &mut self,
+        deadline: Instant,
+        phase: TimeoutPhase,
+    ) -> Result<Message, NodeConnectionAgentError> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(NodeConnectionAgentError::Timeout { phase });
+        }
+
+        let read_timeout = match self.config.read_timeout {
+            Some(read_timeout) => read_timeout.min(remaining),
+            None => remaining,
+        };
+        self.stream.set_read_timeout(Some(read_timeout))?;
+        let result = self.read_message();
+        self.stream.set_read_timeout(self.config.read_timeout)?;
+
+        match result {
+            Err(NodeConnectionAgentError::Timeout {
+                phase: TimeoutPhase::Read,
+            }) if Instant::now() >= deadline => Err(NodeConnectionAgentError::Timeout { phase }),
+            result => result,
         }
+    }
+
+    fn run_keepalive_loop(
+        &mut self,
+        config: &KeepaliveConfig,
+        on_message: &mut dyn FnMut(Message) -> ControlFlow<()>,
+    ) -> Result<(), NodeConnectionAgentError> {
+        let mut next_ping = Instant::now();
+        loop {
+            let now = Instant::now();
+            if now >= next_ping {
+                if self.pending_ping.is_some() {
+                    self.missed_pongs += 1;
+                    if self.missed_pongs >= config.max_missed_pongs {
+                        return Err(NodeConnectionAgentError::PingTimeout(self.missed_pongs));
+                    }
+                }
+                self.send_ping()?;
+                next_ping = now + config.ping_interval;
+            }
+
+            let time_to_next_ping = next_ping.saturating_duration_since(Instant::now());
+            self.stream
+                .set_read_timeout(Some(time_to_next_ping.max(Duration::from_millis(1))))?;
+
+            match self.read_message() {
+                Ok(Message::Ping(ping)) => {
+                    self.write_message(&Message::Pong(Pong { nonce: ping.nonce }))?
+                }
+                Ok(Message::Pong(pong)) => self.handle_pong(&pong),
+                Ok(message) => {
+                    if on_message(message).is_break() {
+                        return Ok(());
+                    }
+                }
+                Err(NodeConnectionAgentError::Timeout {
+                    phase: TimeoutPhase::Read,
+                }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn update_address_manager<F: FnOnce(&mut AddressManager)>(&self, update: F) {
+        if let Some(address_manager) = &self.address_manager {
+            update(
+                &mut address_manager
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+        }
+    }
+
+    fn handle_pong(&mut self, pong: &Pong) {
+        if let Some((nonce, sent_at)) = self.pending_ping {
+            if nonce == pong.nonce {
+                self.latency = Some(sent_at.elapsed());
+                self.missed_pongs = 0;
+                self.pending_ping = None;
+            }
+        }
+    }
+
+    fn receive_more(&mut self) -> Result<(), NodeConnectionAgentError> {
+        let mut chunk = [0; RECEIVE_CHUNK_SIZE];
+        let read_size = self
+            .stream
+            .read(&mut chunk)
+            .map_err(|e| self.stream_error(e, TimeoutPhase::Read))?;
+        if read_size == 0 {
+            if self.cancelled.load(Ordering::SeqCst) {
+                return Err(NodeConnectionAgentError::Cancelled);
+            }
+            return Err(NodeConnectionAgentError::ConnectionClosed);
+        }
+        self.receive_buffer.extend_from_slice(&chunk[0..read_size]);
 
         Ok(())
     }
 
-    fn get_unix_timestamp() -> Result<u64, SystemTimeError> {
-        let duration = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    fn stream_error(&self, error: std::io::Error, phase: TimeoutPhase) -> NodeConnectionAgentError {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return NodeConnectionAgentError::Cancelled;
+        }
+
+        NodeConnectionAgentError::from_io_error(error, phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogecoin::tests::{mine_child, EASY_NETWORK, MAIN_GENESIS_HEADER};
+    use block_header::BlockHeader;
+    use header::HEADER_SIZE;
+    use messages::addr::Addr;
+    use messages::getdata::GetData;
+    use messages::headers::{Headers, HeadersEntry};
+    use messages::inv::{Inv, InvType, Inventory};
+    use messages::notfound::NotFound;
+    use messages::version::IpData;
+    use service_flags::ServiceFlags;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn connect_to_fake_node<F>(node: F) -> NodeConnectionAgent
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            node(stream);
+        });
+
+        NodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", port).unwrap()
+    }
+
+    fn version_frame() -> Vec<u8> {
+        let data = VersionMessageData::from_network_bytes(&crate::dogecoin::tests::VERSION_MESSAGE)
+            .unwrap();
+        Message::Version(data)
+            .to_network_bytes(&NetworkParams::TEST)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read_message_split_across_segments() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            let mut frame = version_frame();
+            frame.extend(
+                Message::Verack
+                    .to_network_bytes(&NetworkParams::TEST)
+                    .unwrap(),
+            );
+            for chunk in frame.chunks(7) {
+                stream.write_all(chunk).unwrap();
+                stream.flush().unwrap();
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        let data = agent.receive_version().unwrap();
+        assert_eq!(70015, data.protocol_version);
+        assert_eq!(
+            Some(b"/Shibetoshi:1.14.6/".as_slice()),
+            data.client_name.as_deref()
+        );
+        assert!(agent.read_version_ack().is_ok());
+    }
+
+    #[test]
+    fn test_read_message_checksum_mismatch() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            let mut frame = version_frame();
+            let last = frame.len() - 1;
+            frame[last] ^= 0xFF;
+            stream.write_all(&frame).unwrap();
+        });
+
+        let result = agent.read_message();
+        assert!(matches!(
+            result,
+            Err(NodeConnectionAgentError::ChecksumMismatch {
+                expected: [0xA2, 0xBB, 0x58, 0x1C],
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_message_checksum_not_verified() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut frame = version_frame();
+            frame[HEADER_SIZE - 1] ^= 0xFF;
+            stream.write_all(&frame).unwrap();
+        });
+        let config = ConnectionConfig {
+            verify_checksums: false,
+            ..Default::default()
+        };
+        let mut agent =
+            NodeConnectionAgent::new_with_config(NetworkParams::TEST, "127.0.0.1", port, config)
+                .unwrap();
+
+        assert!(agent.receive_version().is_ok());
+    }
+
+    #[test]
+    fn test_request_addresses() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            let addr = Addr {
+                addresses: vec![
+                    TimestampedIpData {
+                        timestamp: 1681266146,
+                        ip_data: IpData {
+                            node_services: ServiceFlags::NODE_NETWORK,
+                            ip_address: "52.77.231.41".parse().unwrap(),
+                            port: 44556,
+                        },
+                    };
+                    600
+                ],
+            };
+            for message in [
+                Message::Ping(Ping { nonce: 7 }),
+                Message::Addr(addr.clone()),
+                Message::SendHeaders,
+                Message::Addr(addr),
+            ] {
+                stream
+                    .write_all(&message.to_network_bytes(&NetworkParams::TEST).unwrap())
+                    .unwrap();
+            }
+            let mut buffer = [0; 1024];
+            while matches!(stream.read(&mut buffer), Ok(size) if size > 0) {}
+        });
+
+        let address_manager = Arc::new(Mutex::new(AddressManager::with_seed(0)));
+        agent.set_address_manager(address_manager.clone());
+
+        let addresses = agent.request_addresses(Duration::from_millis(200)).unwrap();
+        assert_eq!(MAX_ADDR_TO_SEND, addresses.len());
+        assert_eq!(1, address_manager.lock().unwrap().new_count());
+        assert_eq!(Message::SendHeaders, agent.read_message().unwrap());
+
+        let addresses = agent.request_addresses(Duration::from_millis(50)).unwrap();
+        assert!(addresses.is_empty());
+    }
+
+    #[test]
+    fn test_request_addresses_keeps_partial_results() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            let addr = Addr {
+                addresses: vec![TimestampedIpData {
+                    timestamp: 1681266146,
+                    ip_data: IpData {
+                        node_services: ServiceFlags::NODE_NETWORK,
+                        ip_address: "52.77.231.41".parse().unwrap(),
+                        port: 44556,
+                    },
+                }],
+            };
+            for message in [
+                // Over MAX_ADDR_TO_SEND, fails to decode but is only this one message
+                Message::Unknown {
+                    command: "addr".to_string(),
+                    payload: vec![0xFD, 0xE9, 0x03],
+                },
+                Message::Addr(addr),
+                Message::SendHeaders,
+            ] {
+                stream
+                    .write_all(&message.to_network_bytes(&NetworkParams::TEST).unwrap())
+                    .unwrap();
+            }
+        });
+
+        let addresses = agent.request_addresses(Duration::from_secs(5)).unwrap();
+        assert_eq!(1, addresses.len());
+        assert_eq!(Message::SendHeaders, agent.read_message().unwrap());
+        assert!(matches!(
+            agent.read_message(),
+            Err(NodeConnectionAgentError::ConnectionClosed)
+        ));
+        assert!(agent.request_addresses(Duration::from_secs(5)).is_err());
+    }
+
+    #[test]
+    fn test_inventory_exchange() {
+        let inventory = vec![Inventory {
+            inv_type: InvType::Block,
+            hash: Hash256::from_bytes(NetworkParams::TEST.genesis_hash),
+        }];
+        let announced = inventory.clone();
+        let mut agent = connect_to_fake_node(move |stream| {
+            let mut node = NodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            node.write_message(&Message::Inv(Inv {
+                inventory: announced,
+            }))
+            .unwrap();
+
+            // Pretend we pruned everything that was asked for
+            if let Ok(Message::GetData(request)) = node.read_message() {
+                node.write_message(&Message::NotFound(NotFound {
+                    inventory: request.inventory,
+                }))
+                .unwrap();
+            }
+        });
+
+        let Message::Inv(inv) = agent.read_message().unwrap() else {
+            panic!("expected inv");
+        };
+        assert_eq!(inventory, inv.inventory);
+        agent
+            .write_message(&Message::GetData(GetData {
+                inventory: inv.inventory,
+            }))
+            .unwrap();
+        assert_eq!(
+            Message::NotFound(NotFound { inventory }),
+            agent.read_message().unwrap()
+        );
+    }
+
+    // Well below MAX_HEADERS_RESULTS, so syncing takes several rounds without mining much
+    const SERVED_PER_REQUEST: usize = 100;
+
+    const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn serve_headers(stream: TcpStream, chain: Vec<BlockHeader>) {
+        let mut node = NodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+        node.write_message(&Message::SendHeaders).unwrap();
+        let known: Vec<Hash256> = std::iter::once(MAIN_GENESIS_HEADER.hash())
+            .chain(chain.iter().map(BlockHeader::hash))
+            .collect();
+        while let Ok(message) = node.read_message() {
+            let Message::GetHeaders(request) = message else {
+                continue;
+            };
+            // Serve what follows the first locator hash we know, like Core does
+            let start = request
+                .locator
+                .iter()
+                .find_map(|hash| known.iter().position(|known| known == hash))
+                .unwrap_or(0);
+            let headers = chain[start..]
+                .iter()
+                .take(SERVED_PER_REQUEST)
+                .map(|header| HeadersEntry {
+                    header: *header,
+                    aux_pow: None,
+                })
+                .collect();
+            node.write_message(&Message::Headers(Headers { headers }))
+                .unwrap();
+        }
+    }
+
+    fn build_chain(length: u32) -> Vec<BlockHeader> {
+        let mut prev_block = MAIN_GENESIS_HEADER.hash();
+        (0..length)
+            .map(|height| {
+                let header = mine_child(prev_block, MAIN_GENESIS_HEADER.time + height * 60);
+                prev_block = header.hash();
+                header
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sync_headers() {
+        let served = build_chain(250);
+        let expected = served.clone();
+        let mut agent = connect_to_fake_node(move |stream| serve_headers(stream, served));
+
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        agent.sync_headers(&mut chain, 250, SYNC_TIMEOUT).unwrap();
+        assert_eq!(250, chain.height());
+        assert_eq!(expected.as_slice(), chain.headers());
+        assert_eq!(Message::SendHeaders, agent.read_message().unwrap());
+
+        // Peer has nothing past its tip, sync stops instead of spinning
+        agent.sync_headers(&mut chain, 300, SYNC_TIMEOUT).unwrap();
+        assert_eq!(250, chain.height());
+    }
+
+    #[test]
+    fn test_sync_headers_rejects_low_work_chain() {
+        let served = build_chain(2);
+        let mut agent = connect_to_fake_node(move |stream| serve_headers(stream, served));
+
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        // Reaching the requested height is fine, even below the minimum
+        agent.sync_headers(&mut chain, 2, SYNC_TIMEOUT).unwrap();
+        assert_eq!(2, chain.height());
+
+        // Peer's whole chain is below the network minimum
+        assert!(matches!(
+            agent.sync_headers(&mut chain, 10, SYNC_TIMEOUT),
+            Err(NodeConnectionAgentError::HeaderChainFailure(
+                HeaderChainError::InsufficientChainWork { .. }
+            ))
+        ));
+        assert_eq!(2, chain.height());
+    }
+
+    #[test]
+    fn test_sync_headers_deadline() {
+        let mut agent = connect_to_fake_node(|stream| {
+            let mut node = NodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            // Never answers getheaders, but keeps every single read well under its timeout
+            while node
+                .write_message(&Message::Ping(Ping { nonce: 7 }))
+                .is_ok()
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let started = Instant::now();
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        assert!(matches!(
+            agent.sync_headers(&mut chain, 10, Duration::from_millis(200)),
+            Err(NodeConnectionAgentError::Timeout {
+                phase: TimeoutPhase::HeaderSync
+            })
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(0, chain.height());
+    }
+
+    #[test]
+    fn test_sync_headers_rejects_disconnected_chain() {
+        let mut served = build_chain(10);
+        served[5].prev_block = Hash256::ZERO;
+        let mut agent = connect_to_fake_node(move |stream| serve_headers(stream, served));
+
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        assert!(matches!(
+            agent.sync_headers(&mut chain, 10, SYNC_TIMEOUT),
+            Err(NodeConnectionAgentError::HeaderChainFailure(
+                HeaderChainError::DoesNotConnect { .. }
+            ))
+        ));
+        assert_eq!(5, chain.height());
+    }
+
+    #[test]
+    fn test_sync_headers_rejects_bogus_work() {
+        let too_easy = build_chain(10);
+        let mut served = too_easy.clone();
+        // Claims far more work than was actually done
+        served[3].bits = 0x1b0404cb;
+        let mut agent = connect_to_fake_node(move |stream| serve_headers(stream, served));
+
+        let mut chain = HeaderChain::new(&EASY_NETWORK);
+        assert!(matches!(
+            agent.sync_headers(&mut chain, 10, SYNC_TIMEOUT),
+            Err(NodeConnectionAgentError::HeaderChainFailure(
+                HeaderChainError::InvalidProofOfWork(_, ProofOfWorkError::HashAboveTarget { .. })
+            ))
+        ));
+        assert_eq!(3, chain.height());
+
+        // Real networks don't accept targets this easy in the first place
+        let mut agent = connect_to_fake_node(move |stream| serve_headers(stream, too_easy));
+        let mut chain = HeaderChain::new(&NetworkParams::MAIN);
+        assert!(matches!(
+            agent.sync_headers(&mut chain, 10, SYNC_TIMEOUT),
+            Err(NodeConnectionAgentError::HeaderChainFailure(
+                HeaderChainError::TargetAboveLimit { .. }
+            ))
+        ));
+        assert_eq!(0, chain.height());
+    }
+
+    #[test]
+    fn test_read_message_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(&version_frame()).unwrap();
+        });
+        let config = ConnectionConfig {
+            max_message_size: 64,
+            ..Default::default()
+        };
+        let mut agent =
+            NodeConnectionAgent::new_with_config(NetworkParams::TEST, "127.0.0.1", port, config)
+                .unwrap();
+
+        let result = agent.read_message();
+        assert!(matches!(
+            result,
+            Err(NodeConnectionAgentError::MessageTooLarge(105))
+        ));
+    }
+
+    #[test]
+    fn test_read_message_connection_closed() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            stream.write_all(&version_frame()[0..10]).unwrap();
+        });
+
+        let result = agent.read_message();
+        assert!(matches!(
+            result,
+            Err(NodeConnectionAgentError::ConnectionClosed)
+        ));
+    }
+
+    #[test]
+    fn test_keep_alive_answers_pings_and_measures_latency() {
+        let mut agent = connect_to_fake_node(|stream| {
+            let mut node = NodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            node.write_message(&Message::Ping(Ping { nonce: 7 }))
+                .unwrap();
+            loop {
+                match node.read_message() {
+                    Ok(Message::Ping(ping)) => node
+                        .write_message(&Message::Pong(Pong { nonce: ping.nonce }))
+                        .unwrap(),
+                    Ok(Message::Pong(pong)) => {
+                        assert_eq!(7, pong.nonce);
+                        node.write_message(&Message::SendHeaders).unwrap();
+                    }
+                    _ => break,
+                }
+            }
+        });
+
+        let config = KeepaliveConfig {
+            ping_interval: Duration::from_millis(10),
+            max_missed_pongs: 3,
+        };
+        let mut received = Vec::new();
+        let result = agent.keep_alive(&config, |message| {
+            received.push(message);
+            ControlFlow::Break(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(vec![Message::SendHeaders], received);
+        assert!(agent.latency().is_some());
+    }
+
+    #[test]
+    fn test_keep_alive_drops_unresponsive_peer() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            let mut buffer = [0; 1024];
+            while matches!(stream.read(&mut buffer), Ok(size) if size > 0) {}
+        });
+
+        let config = KeepaliveConfig {
+            ping_interval: Duration::from_millis(5),
+            max_missed_pongs: 2,
+        };
+        let result = agent.keep_alive(&config, |_| ControlFlow::Continue(()));
+
+        assert!(matches!(
+            result,
+            Err(NodeConnectionAgentError::PingTimeout(2))
+        ));
+        assert!(agent.latency().is_none());
+    }
+
+    #[test]
+    fn test_handshake_verack_before_version() {
+        let mut agent = connect_to_fake_node(|stream| {
+            let mut node = NodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            assert_eq!("version", node.read_message().unwrap().command());
+            node.write_message(&Message::Verack).unwrap();
+            node.write_message(&Message::Ping(Ping { nonce: 3 }))
+                .unwrap();
+            let mut bytes = version_frame();
+            bytes.extend(
+                Message::SendHeaders
+                    .to_network_bytes(&NetworkParams::TEST)
+                    .unwrap(),
+            );
+            node.stream.write_all(&bytes).unwrap();
+
+            assert_eq!(
+                Message::Pong(Pong { nonce: 3 }),
+                node.read_message().unwrap()
+            );
+            assert_eq!(Message::Verack, node.read_message().unwrap());
+        });
+
+        let outcome = agent.handshake().unwrap();
+        assert_eq!(
+            Some(b"/Shibetoshi:1.14.6/".as_slice()),
+            outcome.peer_version.client_name.as_deref()
+        );
+        assert_eq!(70015, outcome.protocol_version);
+        assert_eq!(Message::SendHeaders, agent.read_message().unwrap());
+    }
+
+    #[test]
+    fn test_connect_ipv6_and_hostname() {
+        let listener = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener,
+            // IPv6 loopback is not available everywhere
+            Err(_) => return,
+        };
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut node = NodeConnectionAgent::from_stream(NetworkParams::TEST, stream).unwrap();
+            node.accept_handshake().unwrap();
+        });
+
+        let mut agent = NodeConnectionAgent::connect(NetworkParams::TEST, address).unwrap();
+        assert_eq!(address, agent.peer_address());
+        assert!(agent.handshake().is_ok());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            listener.accept().unwrap();
+        });
+        let agent = NodeConnectionAgent::new(NetworkParams::TEST, "localhost", port);
+        assert!(agent.is_ok());
+    }
+
+    #[test]
+    fn test_connect_to_network() {
+        let listener =
+            node_listener::NodeListener::bind(NetworkParams::TEST, "127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let _ = listener
+                .accept()
+                .and_then(|mut agent| agent.accept_handshake());
+        });
+        let closed_address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let network = NetworkParams {
+            dns_seeds: &["unreachable.example", "seed.example"],
+            ..NetworkParams::TEST
+        };
+        let resolver = |host: &str, _: u16| match host {
+            "seed.example" => Ok(vec![closed_address, address]),
+            _ => Err(std::io::Error::new(ErrorKind::NotFound, host.to_string())),
+        };
+        let config = ConnectionConfig::for_network(&network);
+        let (agent, outcome) =
+            NodeConnectionAgent::connect_to_network_with_config(network, &resolver, config)
+                .unwrap();
+        assert_eq!(address, agent.peer_address());
+        assert_eq!(70015, outcome.protocol_version);
+
+        let config = ConnectionConfig::for_network(&NetworkParams::REGTEST);
+        assert!(matches!(
+            NodeConnectionAgent::connect_to_network_with_config(
+                NetworkParams::REGTEST,
+                &resolver,
+                config
+            ),
+            Err(NodeConnectionAgentError::NoCandidatePeers)
+        ));
+    }
+
+    #[test]
+    fn test_handshake_feeds_address_manager() {
+        let listener =
+            node_listener::NodeListener::bind(NetworkParams::TEST, "127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let _ = listener
+                .accept()
+                .and_then(|mut agent| agent.accept_handshake());
+        });
+
+        let address_manager = Arc::new(Mutex::new(AddressManager::with_seed(0)));
+        let mut agent =
+            NodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", address.port()).unwrap();
+        agent.set_address_manager(address_manager.clone());
+        agent.handshake().unwrap();
+
+        let mut address_manager = address_manager.lock().unwrap();
+        assert_eq!(1, address_manager.tried_count());
+        let entry = address_manager.select().unwrap();
+        assert_eq!(address, entry.address);
+        assert!(entry.last_success.is_some());
+    }
+
+    #[test]
+    fn test_handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            while matches!(stream.read(&mut buffer), Ok(size) if size > 0) {}
+        });
+
+        let config = ConnectionConfig {
+            read_timeout: Some(Duration::from_millis(200)),
+            handshake_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut agent =
+            NodeConnectionAgent::connect_with_config(NetworkParams::TEST, address, config).unwrap();
+        let started_at = Instant::now();
+        assert!(matches!(
+            agent.handshake(),
+            Err(NodeConnectionAgentError::Timeout {
+                phase: TimeoutPhase::Handshake
+            })
+        ));
+        assert!(started_at.elapsed() >= Duration::from_millis(50));
+
+        assert!(matches!(
+            agent.read_message(),
+            Err(NodeConnectionAgentError::Timeout {
+                phase: TimeoutPhase::Read
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cancel_blocked_read() {
+        let mut agent = connect_to_fake_node(|mut stream| {
+            let mut buffer = [0; 1024];
+            while matches!(stream.read(&mut buffer), Ok(size) if size > 0) {}
+        });
+
+        let handle = agent.cancellation_handle().unwrap();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            handle.cancel();
+        });
 
-        Ok(duration.as_secs())
+        assert!(matches!(
+            agent.read_message(),
+            Err(NodeConnectionAgentError::Cancelled)
+        ));
+        canceller.join().unwrap();
     }
 }
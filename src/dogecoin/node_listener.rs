@@ -0,0 +1,160 @@
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+use super::*;
+use connection_config::ConnectionConfig;
+use errors::*;
+use node_connection_agent::NodeConnectionAgent;
+
+// Answers inbound connections as a Dogecoin node would, handy as a fake peer in tests.
+// Accepted agents haven't done the handshake yet, run accept_handshake on each one's own
+// thread or task so a peer that never answers doesn't hold up the others.
+
+pub struct NodeListener {
+    config: ConnectionConfig,
+    listener: TcpListener,
+    network: NetworkParams,
+}
+
+impl NodeListener {
+    pub fn bind<A: ToSocketAddrs>(
+        network: NetworkParams,
+        address: A,
+    ) -> Result<Self, NodeConnectionAgentError> {
+        Ok(Self {
+            config: ConnectionConfig::for_network(&network),
+            listener: TcpListener::bind(address)?,
+            network,
+        })
+    }
+
+    pub fn set_config(&mut self, config: ConnectionConfig) {
+        self.config = config;
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, NodeConnectionAgentError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn accept(&self) -> Result<NodeConnectionAgent, NodeConnectionAgentError> {
+        let (stream, _) = self.listener.accept()?;
+
+        NodeConnectionAgent::from_stream_with_config(
+            self.network.clone(),
+            stream,
+            self.config.clone(),
+        )
+    }
+
+    pub fn incoming(
+        &self,
+    ) -> impl Iterator<Item = Result<NodeConnectionAgent, NodeConnectionAgentError>> + '_ {
+        std::iter::repeat_with(move || self.accept())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::Message;
+    use std::net::TcpStream;
+    use std::thread;
+
+    #[test]
+    fn test_accept_handshake() {
+        let listener = NodeListener::bind(NetworkParams::TEST, "127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || {
+            let mut agent =
+                NodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", port).unwrap();
+            let outcome = agent.handshake().unwrap();
+            agent.write_message(&Message::SendHeaders).unwrap();
+            outcome
+        });
+
+        let mut session = listener.accept().unwrap();
+        let outcome = session.accept_handshake().unwrap();
+        assert_eq!(
+            Some(b"/Shibetoshi:1.14.6/".as_slice()),
+            outcome.peer_version.client_name.as_deref()
+        );
+        assert_eq!(Message::SendHeaders, session.read_message().unwrap());
+
+        let client_outcome = client.join().unwrap();
+        assert_eq!(70015, client_outcome.protocol_version);
+    }
+
+    #[test]
+    fn test_custom_network() {
+        const FORK: NetworkParams = NetworkParams {
+            name: "fork",
+            magic: [0xFB, 0xC0, 0xB6, 0xDB],
+            default_port: 9333,
+            dns_seeds: &[],
+            protocol_version: 70002,
+            genesis_hash: [0; 32],
+            pow_limit_bits: 0x207fffff,
+            aux_pow_chain_id: 0x0063,
+            min_chain_work: [0; 32],
+        };
+        let listener = NodeListener::bind(FORK, "127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || {
+            let mut agent = NodeConnectionAgent::new(FORK, "127.0.0.1", port).unwrap();
+            agent.handshake().unwrap()
+        });
+
+        let outcome = listener.accept().unwrap().accept_handshake().unwrap();
+        assert_eq!(70002, outcome.peer_version.protocol_version);
+        assert_eq!(70002, client.join().unwrap().protocol_version);
+    }
+
+    #[test]
+    fn test_reject_other_network() {
+        let listener = NodeListener::bind(NetworkParams::TEST, "127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || {
+            let mut agent =
+                NodeConnectionAgent::new(NetworkParams::MAIN, "127.0.0.1", port).unwrap();
+            agent.handshake()
+        });
+
+        let mut session = listener.incoming().next().unwrap().unwrap();
+        assert!(matches!(
+            session.accept_handshake(),
+            Err(NodeConnectionAgentError::UnexpectedNetworkMagic(
+                [0xFC, 0xC1, 0xB7, 0xDC],
+                [0xC0, 0xC0, 0xC0, 0xC0]
+            ))
+        ));
+        drop(session);
+
+        assert!(client.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_silent_peer_does_not_block_others() {
+        let listener = NodeListener::bind(NetworkParams::TEST, "127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Connects and never says a word
+        let silent = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let client = thread::spawn(move || {
+            let mut agent =
+                NodeConnectionAgent::new(NetworkParams::TEST, "127.0.0.1", port).unwrap();
+            agent.handshake().unwrap()
+        });
+
+        let mut sessions = listener.incoming().take(2).map(Result::unwrap);
+        let mut waiting = sessions.next().unwrap();
+        let waiting = thread::spawn(move || waiting.accept_handshake());
+        let outcome = sessions.next().unwrap().accept_handshake().unwrap();
+        assert_eq!(70015, outcome.protocol_version);
+        assert_eq!(70015, client.join().unwrap().protocol_version);
+
+        drop(silent);
+        assert!(waiting.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+
+use super::*;
+use hash256::Hash256;
+
+// 256-bit proof of work target, big-endian so that derived ordering compares numerically
+
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Target([u8; 32]);
+
+impl Target {
+    // Like arith_uint256::SetCompact, but refusing what CheckProofOfWork would refuse anyway
+    pub fn from_compact(bits: u32) -> Result<Target, ProofOfWorkError> {
+        let exponent = (bits >> 24) as usize;
+        let mantissa = bits & 0x007f_ffff;
+
+        if mantissa != 0 && bits & 0x0080_0000 != 0 {
+            return Err(ProofOfWorkError::NegativeTarget(bits));
+        }
+        if mantissa != 0
+            && (exponent > 34
+                || (mantissa > 0xff && exponent > 33)
+                || (mantissa > 0xffff && exponent > 32))
+        {
+            return Err(ProofOfWorkError::TargetOverflow(bits));
+        }
+
+        // Mantissa times 256^(exponent - 3), bytes shifted out to the right are dropped
+        let mut target = [0; 32];
+        for (index, byte) in mantissa.to_le_bytes()[..3].iter().enumerate() {
+            match (exponent + index).checked_sub(3) {
+                Some(position) if position < 32 => target[31 - position] = *byte,
+                _ => {}
+            }
+        }
+        let target = Target(target);
+        if target == Target([0; 32]) {
+            return Err(ProofOfWorkError::ZeroTarget(bits));
+        }
+
+        Ok(target)
+    }
+
+    pub fn is_met_by(&self, hash: &Hash256) -> bool {
+        let mut value = *hash.as_bytes();
+        value.reverse();
+
+        value <= self.0
+    }
+
+    // Like GetBlockProof, 2^256 / (target + 1) computed as ~target / (target + 1) + 1
+    pub fn work(&self) -> ChainWork {
+        let mut target = ChainWork::ZERO;
+        for (limb, chunk) in target.0.iter_mut().zip(self.0.chunks_exact(8)) {
+            *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+
+        ChainWork(target.0.map(|limb| !limb))
+            .div(target.saturating_add(ChainWork::ONE))
+            .saturating_add(ChainWork::ONE)
+    }
+}
+
+// Expected number of hashes behind a chain, 256 bits like Core's nChainWork.
+// Limbs are most significant first so that derived ordering compares numerically
+
+#[derive(Clone, Copy, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ChainWork([u64; 4]);
+
+impl ChainWork {
+    pub const ZERO: ChainWork = ChainWork([0; 4]);
+    const ONE: ChainWork = ChainWork([0, 0, 0, 1]);
+
+    // Internal byte order, the same as NetworkParams::genesis_hash
+    pub fn from_le_bytes(bytes: [u8; 32]) -> ChainWork {
+        let mut work = ChainWork::ZERO;
+        for (limb, chunk) in work.0.iter_mut().rev().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+
+        work
+    }
+
+    pub fn saturating_add(self, other: ChainWork) -> ChainWork {
+        let mut sum = ChainWork::ZERO;
+        let mut carry = false;
+        for index in (0..4).rev() {
+            let (value, first_overflow) = self.0[index].overflowing_add(other.0[index]);
+            let (value, second_overflow) = value.overflowing_add(carry as u64);
+            sum.0[index] = value;
+            carry = first_overflow || second_overflow;
+        }
+
+        if carry {
+            ChainWork([u64::MAX; 4])
+        } else {
+            sum
+        }
+    }
+
+    // Shift and subtract long division, divisor must not be zero
+    fn div(mut self, mut divisor: ChainWork) -> ChainWork {
+        let mut quotient = ChainWork::ZERO;
+        if self < divisor {
+            return quotient;
+        }
+
+        let shift = divisor.leading_zeros() - self.leading_zeros();
+        for _ in 0..shift {
+            divisor = divisor.shl1();
+        }
+        for _ in 0..=shift {
+            quotient = quotient.shl1();
+            if self >= divisor {
+                self = self.sub(divisor);
+                quotient.0[3] |= 1;
+            }
+            divisor = divisor.shr1();
+        }
+
+        quotient
+    }
+
+    fn sub(self, other: ChainWork) -> ChainWork {
+        let mut difference = ChainWork::ZERO;
+        let mut borrow = false;
+        for index in (0..4).rev() {
+            let (value, first_borrow) = self.0[index].overflowing_sub(other.0[index]);
+            let (value, second_borrow) = value.overflowing_sub(borrow as u64);
+            difference.0[index] = value;
+            borrow = first_borrow || second_borrow;
+        }
+
+        difference
+    }
+
+    fn shl1(self) -> ChainWork {
+        let mut shifted = ChainWork::ZERO;
+        for index in 0..4 {
+            shifted.0[index] =
+                self.0[index] << 1 | self.0.get(index + 1).map_or(0, |low| low >> 63);
+        }
+
+        shifted
+    }
+
+    fn shr1(self) -> ChainWork {
+        let mut shifted = ChainWork::ZERO;
+        for index in 0..4 {
+            let high = if index == 0 {
+                0
+            } else {
+                self.0[index - 1] << 63
+            };
+            shifted.0[index] = self.0[index] >> 1 | high;
+        }
+
+        shifted
+    }
+
+    fn leading_zeros(&self) -> u32 {
+        let mut zeros = 0;
+        for limb in self.0 {
+            zeros += limb.leading_zeros();
+            if limb != 0 {
+                break;
+            }
+        }
+
+        zeros
+    }
+}
+
+impl fmt::Display for ChainWork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x")?;
+        for limb in self.0 {
+            write!(f, "{:016x}", limb)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ChainWork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChainWork({})", self)
+    }
+}
+
+impl fmt::Debug for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Target(")?;
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target_from_hex(hex: &str) -> Target {
+        let mut bytes = *Hash256::from_hex(hex).as_bytes();
+        bytes.reverse();
+        Target(bytes)
+    }
+
+    #[test]
+    fn test_from_compact() {
+        assert_eq!(
+            target_from_hex("00000fffff000000000000000000000000000000000000000000000000000000"),
+            Target::from_compact(NetworkParams::MAIN.pow_limit_bits).unwrap()
+        );
+        assert_eq!(
+            target_from_hex("0000000000000000000000000000000000000000000000000000000000000012"),
+            Target::from_compact(0x01123456).unwrap()
+        );
+        assert_eq!(
+            target_from_hex("0000000000000000000000000000000000000000000000000000000092340000"),
+            Target::from_compact(0x05009234).unwrap()
+        );
+
+        assert_eq!(
+            Err(ProofOfWorkError::ZeroTarget(0x01003456)),
+            Target::from_compact(0x01003456)
+        );
+        assert_eq!(
+            Err(ProofOfWorkError::NegativeTarget(0x04923456)),
+            Target::from_compact(0x04923456)
+        );
+        assert_eq!(
+            Err(ProofOfWorkError::TargetOverflow(0xff123456)),
+            Target::from_compact(0xff123456)
+        );
+    }
+
+    #[test]
+    fn test_is_met_by() {
+        let target = Target::from_compact(0x1e0ffff0).unwrap();
+        assert!(target.is_met_by(&Hash256::ZERO));
+        assert!(target.is_met_by(&Hash256::from_hex(
+            "00000ffff0000000000000000000000000000000000000000000000000000000"
+        )));
+        assert!(!target.is_met_by(&Hash256::from_hex(
+            "00000ffff0000000000000000000000000000000000000000000000000000001"
+        )));
+    }
+
+    #[test]
+    fn test_work() {
+        let work = |bits| Target::from_compact(bits).unwrap().work();
+        assert_eq!(ChainWork([0, 0, 0, 0x100001]), work(0x1e0fffff));
+        assert_eq!(ChainWork([0, 0, 0, 0x100010]), work(0x1e0ffff0));
+        assert_eq!(ChainWork([0, 0, 0, 0x3fb3ab764c00]), work(0x1b0404cb));
+        assert_eq!(ChainWork([0, 0, 0, 2]), work(0x207fffff));
+        assert_eq!(ChainWork([0, 0, 0, 1]), work(0x2100ffff));
+        // Target of one, the hardest there is
+        assert_eq!(ChainWork([1 << 63, 0, 0, 0]), work(0x03000001));
+    }
+
+    #[test]
+    fn test_chain_work_arithmetic() {
+        let mut bytes = [0; 32];
+        bytes[0] = 0xa0;
+        bytes[9] = 0x01;
+        assert_eq!(
+            ChainWork([0, 0, 0x100, 0xa0]),
+            ChainWork::from_le_bytes(bytes)
+        );
+        assert_eq!(
+            ChainWork([0, 0, 1, 0]),
+            ChainWork([0, 0, 0, u64::MAX]).saturating_add(ChainWork::ONE)
+        );
+        assert_eq!(
+            ChainWork([u64::MAX; 4]),
+            ChainWork([u64::MAX; 4]).saturating_add(ChainWork::ONE)
+        );
+        assert_eq!(
+            "0x0000000000000000000000000000000000000000000000000000000000100001",
+            ChainWork([0, 0, 0, 0x100001]).to_string()
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+// Turns DNS seed hostnames into candidate peers, swappable so tests don't need real DNS
+pub trait Resolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DnsResolver;
+
+impl Resolver for DnsResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+impl<F> Resolver for F
+where
+    F: Fn(&str, u16) -> io::Result<Vec<SocketAddr>>,
+{
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        self(host, port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolvers() {
+        let addresses = DnsResolver.resolve("127.0.0.1", 22556).unwrap();
+        assert_eq!(vec![SocketAddr::from(([127, 0, 0, 1], 22556))], addresses);
+
+        let stub = |host: &str, port: u16| match host {
+            "seed.multidoge.org" => Ok(vec![SocketAddr::from(([10, 0, 0, 1], port))]),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, host.to_string())),
+        };
+        assert_eq!(
+            vec![SocketAddr::from(([10, 0, 0, 1], 22556))],
+            stub.resolve("seed.multidoge.org", 22556).unwrap()
+        );
+        assert!(stub.resolve("seed2.multidoge.org", 22556).is_err());
+    }
+}
@@ -0,0 +1,639 @@
+use std::fmt::Display;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, Serialize};
+
+use super::*;
+use serializer::{CompactSize, Reader};
+
+// serde data format for the wire encoding: little-endian integers, CompactSize prefixed
+// sequences, maps and strings, fixed size arrays and tuples without any prefix
+
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, NetworkSerializationError> {
+    let mut serializer = Serializer { buffer: Vec::new() };
+    value.serialize(&mut serializer)?;
+
+    Ok(serializer.buffer)
+}
+
+pub fn from_bytes<'de, T: de::Deserialize<'de>>(
+    bytes: &'de [u8],
+) -> Result<T, NetworkSerializationError> {
+    from_reader(&mut Reader::new(bytes))
+}
+
+pub fn from_reader<'de, T: de::Deserialize<'de>>(
+    reader: &mut Reader<'de>,
+) -> Result<T, NetworkSerializationError> {
+    T::deserialize(&mut Deserializer { reader })
+}
+
+impl ser::Error for NetworkSerializationError {
+    fn custom<T: Display>(message: T) -> Self {
+        NetworkSerializationError::Custom(message.to_string())
+    }
+}
+
+impl de::Error for NetworkSerializationError {
+    fn custom<T: Display>(message: T) -> Self {
+        NetworkSerializationError::Custom(message.to_string())
+    }
+
+    fn invalid_value(_: de::Unexpected, _: &dyn de::Expected) -> Self {
+        NetworkSerializationError::UnknownBytes
+    }
+}
+
+fn unsupported(what: &str) -> NetworkSerializationError {
+    NetworkSerializationError::Custom(format!("{} is not supported by the wire format", what))
+}
+
+pub struct Serializer {
+    buffer: Vec<u8>,
+}
+
+impl Serializer {
+    fn write_length(&mut self, length: Option<usize>) -> Result<(), NetworkSerializationError> {
+        let length = length.ok_or_else(|| unsupported("sequence of unknown length"))?;
+        CompactSize(length as u64).write(&mut self.buffer);
+
+        Ok(())
+    }
+}
+
+impl ser::Serializer for &mut Serializer {
+    type Ok = ();
+    type Error = NetworkSerializationError;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = ser::Impossible<(), NetworkSerializationError>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = ser::Impossible<(), NetworkSerializationError>;
+
+    fn serialize_bool(self, value: bool) -> Result<(), Self::Error> {
+        self.buffer.push(value.into());
+        Ok(())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), Self::Error> {
+        self.buffer.push(value);
+        Ok(())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<(), Self::Error> {
+        Err(unsupported("f32"))
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<(), Self::Error> {
+        Err(unsupported("f64"))
+    }
+
+    fn serialize_char(self, _: char) -> Result<(), Self::Error> {
+        Err(unsupported("char"))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Self::Error> {
+        self.serialize_bytes(value.as_bytes())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), Self::Error> {
+        self.write_length(Some(value.len()))?;
+        self.buffer.extend_from_slice(value);
+        Ok(())
+    }
+
+    // Optional fields only ever trail a message, absent ones are simply not sent
+    fn serialize_none(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<(), Self::Error> {
+        Err(unsupported("enum"))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Self::Error> {
+        Err(unsupported("enum"))
+    }
+
+    fn serialize_seq(self, length: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.write_length(length)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(unsupported("enum"))
+    }
+
+    fn serialize_map(self, length: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.write_length(length)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(unsupported("enum"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for &mut Serializer {
+    type Ok = ();
+    type Error = NetworkSerializationError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut Serializer {
+    type Ok = ();
+    type Error = NetworkSerializationError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut Serializer {
+    type Ok = ();
+    type Error = NetworkSerializationError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut Serializer {
+    type Ok = ();
+    type Error = NetworkSerializationError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+    type Error = NetworkSerializationError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub struct Deserializer<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+}
+
+impl<'a, 'de> Deserializer<'a, 'de> {
+    pub fn new(reader: &'a mut Reader<'de>) -> Self {
+        Deserializer { reader }
+    }
+}
+
+impl<'de, 'a, 'b> de::Deserializer<'de> for &'b mut Deserializer<'a, 'de> {
+    type Error = NetworkSerializationError;
+
+    // The format isn't self-describing, the target type has to drive decoding
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(unsupported("deserialize_any"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_bool(self.reader.read_bool()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i8(i8::from_le_bytes(self.reader.read_array()?))
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i16(i16::from_le_bytes(self.reader.read_array()?))
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i32(self.reader.read_i32_le()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i64(self.reader.read_i64_le()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u8(self.reader.read_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u16(self.reader.read_u16_le()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u32(self.reader.read_u32_le()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u64(self.reader.read_u64_le()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(unsupported("f32"))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(unsupported("f64"))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(unsupported("char"))
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    // Only valid UTF-8 ends up in a String, use Vec<u8> for raw peer supplied text
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let length = self.reader.read_compact_size()? as usize;
+        let value = std::str::from_utf8(self.reader.read_bytes(length)?)
+            .map_err(|_| NetworkSerializationError::StringParseError)?;
+
+        visitor.visit_borrowed_str(value)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let length = self.reader.read_compact_size()? as usize;
+        visitor.visit_borrowed_bytes(self.reader.read_bytes(length)?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    // Trailing optional fields are present as long as the payload has bytes left
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.reader.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let length = self.reader.read_compact_size()? as usize;
+        visitor.visit_seq(Access {
+            deserializer: self,
+            remaining: length,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Access {
+            deserializer: self,
+            remaining: length,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(length, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let length = self.reader.read_compact_size()? as usize;
+        visitor.visit_map(Access {
+            deserializer: self,
+            remaining: length,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(unsupported("enum"))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(unsupported("identifier"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(unsupported("deserialize_ignored_any"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct Access<'b, 'a, 'de> {
+    deserializer: &'b mut Deserializer<'a, 'de>,
+    remaining: usize,
+}
+
+impl<'de, 'a, 'b> SeqAccess<'de> for Access<'b, 'a, 'de> {
+    type Error = NetworkSerializationError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    // Don't trust the count for preallocation, every element takes at least one byte
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.min(self.deserializer.reader.remaining()))
+    }
+}
+
+impl<'de, 'a, 'b> de::MapAccess<'de> for Access<'b, 'a, 'de> {
+    type Error = NetworkSerializationError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        self.next_element_seed(seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        seed.deserialize(&mut *self.deserializer)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.min(self.deserializer.reader.remaining()))
+    }
+}
+
+// #[serde(with = "serde_wire::ip_address")], IPv4 travels as IPv4-mapped IPv6
+pub mod ip_address {
+    use std::net::IpAddr;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::super::serializer::{ip_address_to_bytes, slice_to_ip_address};
+
+    pub fn serialize<S: Serializer>(value: &IpAddr, serializer: S) -> Result<S::Ok, S::Error> {
+        ip_address_to_bytes(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<IpAddr, D::Error> {
+        let bytes = <[u8; 16]>::deserialize(deserializer)?;
+
+        // 16 bytes always make up a valid address
+        Ok(slice_to_ip_address(&bytes).unwrap())
+    }
+}
+
+// #[serde(with = "serde_wire::be_u16")], for the few big-endian fields like ports
+pub mod be_u16 {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        value.to_be_bytes().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        Ok(u16::from_be_bytes(<[u8; 2]>::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Sample {
+        version: i32,
+        hashes: Vec<[u8; 2]>,
+        name: String,
+        #[serde(with = "be_u16")]
+        port: u16,
+        relay: Option<bool>,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = [
+            0xFF, 0xFF, 0xFF, 0xFF, 0x02, 0xAA, 0xBB, 0xCC, 0xDD, 0x02, b'o', b'k', 0x1F, 0x90,
+            0x01,
+        ];
+        let sample: Sample = from_bytes(&bytes).unwrap();
+        assert_eq!(
+            Sample {
+                version: -1,
+                hashes: vec![[0xAA, 0xBB], [0xCC, 0xDD]],
+                name: "ok".to_string(),
+                port: 8080,
+                relay: Some(true),
+            },
+            sample
+        );
+        assert_eq!(&bytes, to_bytes(&sample).unwrap().as_slice());
+
+        let sample: Sample = from_bytes(&bytes[..14]).unwrap();
+        assert_eq!(None, sample.relay);
+        assert_eq!(&bytes[..14], to_bytes(&sample).unwrap().as_slice());
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(matches!(
+            from_bytes::<Sample>(&[0x00, 0x00, 0x00, 0x00, 0x03, 0xAA]),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 6,
+                needed: 1
+            })
+        ));
+        assert!(matches!(
+            from_bytes::<Vec<u8>>(&[0xFD, 0x01, 0x00, 0x00]),
+            Err(NetworkSerializationError::CompactSizeError(
+                CompactSizeError::NonCanonical(1)
+            ))
+        ));
+        assert!(matches!(
+            to_bytes(&1.5f64),
+            Err(NetworkSerializationError::Custom(_))
+        ));
+    }
+}
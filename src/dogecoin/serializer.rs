@@ -1,14 +1,25 @@
 use std::mem::size_of;
+use std::net::{IpAddr, Ipv6Addr};
 
 use super::*;
 use errors::*;
 
-pub fn slice_to_ip_address(slice: &[u8]) -> Option<IpAddress> {
-    slice.try_into().ok()
+// IPv4 addresses travel as IPv4-mapped IPv6 (::ffff:a.b.c.d)
+pub fn slice_to_ip_address(slice: &[u8]) -> Option<IpAddr> {
+    let octets: [u8; 16] = slice.try_into().ok()?;
+    let address = Ipv6Addr::from(octets);
+
+    Some(match address.to_ipv4_mapped() {
+        Some(address) => IpAddr::V4(address),
+        None => IpAddr::V6(address),
+    })
 }
 
-pub fn be_slice_to_u16(slice: &[u8]) -> Option<u16> {
-    Some(u16::from_be_bytes(slice.try_into().ok()?))
+pub fn ip_address_to_bytes(ip_address: &IpAddr) -> [u8; 16] {
+    match ip_address {
+        IpAddr::V4(address) => address.to_ipv6_mapped().octets(),
+        IpAddr::V6(address) => address.octets(),
+    }
 }
 
 pub fn slice_to_u16(slice: &[u8]) -> Option<u16> {
@@ -23,29 +34,374 @@ pub fn slice_to_u64(slice: &[u8]) -> Option<u64> {
     Some(u64::from_le_bytes(slice.try_into().ok()?))
 }
 
-pub fn slice_to_string(slice: &[u8]) -> String {
+// SAFE_CHARS_DEFAULT from Core's utilstrencodings
+const SAFE_CHARS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 .,;-_/:?@()";
+
+pub fn is_safe_char(byte: u8) -> bool {
+    SAFE_CHARS.contains(&byte)
+}
+
+// Same as SanitizeString in Core, drops every byte outside of SAFE_CHARS
+pub fn sanitize_string(slice: &[u8]) -> String {
     slice
         .iter()
-        .filter_map(|byte| match *byte {
-            0 => None,
-            _ => Some(*byte as char),
-        })
+        .filter(|byte| is_safe_char(**byte))
+        .map(|byte| *byte as char)
         .collect()
 }
 
+// MAX_SIZE in Dogecoin Core, upper bound for any length or count read off the wire
+pub const MAX_SIZE: u64 = 0x0200_0000;
+
+// Variable length integer prefixing strings and vectors, see WriteCompactSize in Core
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactSize(pub u64);
+
+#[derive(Debug, PartialEq)]
+pub struct CompactSizeResult {
+    pub bytes_read: usize,
+    pub value: u64,
+}
+
+impl CompactSize {
+    pub fn encoded_size(&self) -> usize {
+        if self.0 < 253 {
+            size_of::<u8>()
+        } else if self.0 <= u16::MAX as u64 {
+            size_of::<u16>() + 1
+        } else if self.0 <= u32::MAX as u64 {
+            size_of::<u32>() + 1
+        } else {
+            size_of::<u64>() + 1
+        }
+    }
+
+    pub fn write(&self, buffer: &mut Vec<u8>) {
+        if self.0 < 253 {
+            buffer.push(self.0 as u8);
+        } else if self.0 <= u16::MAX as u64 {
+            buffer.push(253);
+            buffer.extend_from_slice(&(self.0 as u16).to_le_bytes());
+        } else if self.0 <= u32::MAX as u64 {
+            buffer.push(254);
+            buffer.extend_from_slice(&(self.0 as u32).to_le_bytes());
+        } else {
+            buffer.push(255);
+            buffer.extend_from_slice(&self.0.to_le_bytes());
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.encoded_size());
+        self.write(&mut buffer);
+        buffer
+    }
+
+    // Rejects values above MAX_SIZE, same as ReadCompactSize with range_check
+    pub fn read(slice: &[u8]) -> Result<CompactSizeResult, CompactSizeError> {
+        let result = Self::read_unchecked(slice)?;
+        if result.value > MAX_SIZE {
+            return Err(CompactSizeError::TooLarge(result.value));
+        }
+
+        Ok(result)
+    }
+
+    pub fn read_unchecked(slice: &[u8]) -> Result<CompactSizeResult, CompactSizeError> {
+        let first_byte = *slice.first().ok_or(CompactSizeError::BufferTooShort)?;
+        let (value, bytes_read, minimum) = match first_byte {
+            253 => (
+                slice.get(1..3).and_then(slice_to_u16).map(u64::from),
+                3,
+                253,
+            ),
+            254 => (
+                slice.get(1..5).and_then(slice_to_u32).map(u64::from),
+                5,
+                u16::MAX as u64 + 1,
+            ),
+            255 => (
+                slice.get(1..9).and_then(slice_to_u64),
+                9,
+                u32::MAX as u64 + 1,
+            ),
+            _ => (Some(first_byte as u64), 1, 0),
+        };
+        let value = value.ok_or(CompactSizeError::BufferTooShort)?;
+
+        // Every value has exactly one valid encoding, the shortest one
+        if value < minimum {
+            return Err(CompactSizeError::NonCanonical(value));
+        }
+
+        Ok(CompactSizeResult { bytes_read, value })
+    }
+}
+
 pub fn calculate_size_of_serialized_string_and_length_bytes(
     length: usize,
 ) -> Result<usize, CalculateSizeOfSerializedStringAndLengthBytesError> {
-    if length < u8::MAX as usize {
-        Ok(length + size_of::<u8>())
-    } else if length < u16::MAX as usize {
-        Ok(length + size_of::<u16>() + 1)
-    } else if length < u32::MAX as usize {
-        Ok(length + size_of::<u32>() + 1)
-    } else if length < (u64::MAX - 1) as usize {
-        Ok(length + size_of::<u64>() + 1)
-    } else {
-        Err(CalculateSizeOfSerializedStringAndLengthBytesError::StringTooLong)
+    if length as u64 > MAX_SIZE {
+        return Err(CalculateSizeOfSerializedStringAndLengthBytesError::StringTooLong);
+    }
+
+    Ok(length + CompactSize(length as u64).encoded_size())
+}
+
+// Cursor over a received payload, every read is bounds checked and advances the offset
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+pub trait Decode: Sized {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError>;
+}
+
+pub trait Encode {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError>;
+}
+
+// Integers are little-endian on the wire, except for a few fields like ports
+pub trait BigEndian: Sized {
+    fn decode_be(reader: &mut Reader) -> Result<Self, NetworkSerializationError>;
+    fn encode_be(&self, buffer: &mut Vec<u8>);
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, offset: 0 }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], NetworkSerializationError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(length))
+            .ok_or(NetworkSerializationError::BufferTooShort {
+                offset: self.offset,
+                needed: length,
+            })?;
+        self.offset += length;
+
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], NetworkSerializationError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+
+        Ok(array)
+    }
+
+    pub fn read_remaining(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.offset..];
+        self.offset = self.bytes.len();
+
+        bytes
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, NetworkSerializationError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, NetworkSerializationError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16_le(&mut self) -> Result<u16, NetworkSerializationError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u16_be(&mut self) -> Result<u16, NetworkSerializationError> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32_le(&mut self) -> Result<u32, NetworkSerializationError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i32_le(&mut self) -> Result<i32, NetworkSerializationError> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64_le(&mut self) -> Result<u64, NetworkSerializationError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i64_le(&mut self) -> Result<i64, NetworkSerializationError> {
+        Ok(i64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_compact_size(&mut self) -> Result<u64, NetworkSerializationError> {
+        let result = CompactSize::read(&self.bytes[self.offset..]).map_err(|e| match e {
+            CompactSizeError::BufferTooShort => NetworkSerializationError::BufferTooShort {
+                offset: self.offset,
+                needed: self
+                    .bytes
+                    .get(self.offset)
+                    .map_or(1, |first_byte| match first_byte {
+                        253 => 3,
+                        254 => 5,
+                        _ => 9,
+                    }),
+            },
+            e => e.into(),
+        })?;
+        self.offset += result.bytes_read;
+
+        Ok(result.value)
+    }
+
+    pub fn read_var_bytes(&mut self) -> Result<Vec<u8>, NetworkSerializationError> {
+        let length = self.read_compact_size()? as usize;
+
+        Ok(self.read_bytes(length)?.to_vec())
+    }
+
+    // Peer supplied strings are kept as raw bytes, see sanitize_string and UserAgent
+    pub fn read_var_str(&mut self) -> Result<Vec<u8>, NetworkSerializationError> {
+        self.read_var_bytes()
+    }
+
+    pub fn read_ip_address(&mut self) -> Result<IpAddr, NetworkSerializationError> {
+        slice_to_ip_address(self.read_bytes(16)?).ok_or(NetworkSerializationError::UnknownBytes)
+    }
+
+    pub fn read<T: Decode>(&mut self) -> Result<T, NetworkSerializationError> {
+        T::decode(self)
+    }
+
+    pub fn read_compact_vec<T: Decode>(&mut self) -> Result<Vec<T>, NetworkSerializationError> {
+        let count = self.read_compact_size()? as usize;
+
+        self.read_items(count)
+    }
+
+    // For counts with a protocol limit tighter than MAX_SIZE, like MAX_INV_SZ
+    pub fn read_compact_vec_capped<T: Decode>(
+        &mut self,
+        limit: usize,
+    ) -> Result<Vec<T>, NetworkSerializationError> {
+        let count = self.read_compact_size()?;
+        if count > limit as u64 {
+            return Err(NetworkSerializationError::TooManyEntries {
+                count,
+                limit: limit as u64,
+            });
+        }
+
+        self.read_items(count as usize)
+    }
+
+    fn read_items<T: Decode>(&mut self, count: usize) -> Result<Vec<T>, NetworkSerializationError> {
+        // Don't trust the count for preallocation, every element takes at least one byte
+        let mut items = Vec::with_capacity(count.min(self.remaining()));
+        for _ in 0..count {
+            items.push(self.read()?);
+        }
+
+        Ok(items)
+    }
+}
+
+pub fn encode_var_str(value: &[u8], buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+    buffer.extend_from_slice(&value.to_vec().to_dogecoin_bytes()?);
+
+    Ok(())
+}
+
+pub fn encode_compact_vec<T: Encode>(
+    items: &[T],
+    buffer: &mut Vec<u8>,
+) -> Result<(), NetworkSerializationError> {
+    CompactSize(items.len() as u64).write(buffer);
+    for item in items {
+        item.encode(buffer)?;
+    }
+
+    Ok(())
+}
+
+macro_rules! impl_integer_codecs {
+    ($($type:ty),*) => {
+        $(
+            impl Decode for $type {
+                fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+                    Ok(<$type>::from_le_bytes(reader.read_array()?))
+                }
+            }
+
+            impl Encode for $type {
+                fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+                    buffer.extend_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+            }
+
+            impl BigEndian for $type {
+                fn decode_be(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+                    Ok(<$type>::from_be_bytes(reader.read_array()?))
+                }
+
+                fn encode_be(&self, buffer: &mut Vec<u8>) {
+                    buffer.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_codecs!(u8, u16, u32, u64, i32, i64);
+
+impl Decode for bool {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        reader.read_bool()
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        buffer.push((*self).into());
+        Ok(())
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        reader.read_array()
+    }
+}
+
+impl<const N: usize> Encode for [u8; N] {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        buffer.extend_from_slice(self);
+        Ok(())
+    }
+}
+
+impl Decode for IpAddr {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        reader.read_ip_address()
+    }
+}
+
+impl Encode for IpAddr {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        buffer.extend_from_slice(&ip_address_to_bytes(self));
+        Ok(())
     }
 }
 
@@ -55,8 +411,26 @@ pub struct SerializedStringResult {
     pub value: String,
 }
 
+#[derive(PartialEq)]
+pub struct SerializedBytesResult {
+    pub bytes_read: usize,
+    pub value: Vec<u8>,
+}
+
 pub trait SerializeString {
-    fn from_dogecoin_bytes(slice: &[u8]) -> Result<SerializedStringResult, IntegerParsingFailure>;
+    fn from_dogecoin_bytes(
+        slice: &[u8],
+    ) -> Result<SerializedStringResult, NetworkSerializationError>;
+
+    fn to_dogecoin_bytes(
+        &self,
+    ) -> Result<Vec<u8>, CalculateSizeOfSerializedStringAndLengthBytesError>;
+}
+
+pub trait SerializeBytes {
+    fn from_dogecoin_bytes(
+        slice: &[u8],
+    ) -> Result<SerializedBytesResult, NetworkSerializationError>;
 
     fn to_dogecoin_bytes(
         &self,
@@ -64,71 +438,48 @@ pub trait SerializeString {
 }
 
 impl SerializeString for String {
-    fn from_dogecoin_bytes(slice: &[u8]) -> Result<SerializedStringResult, IntegerParsingFailure> {
-        let mut offset: usize = 0;
-        let length: usize;
-
-        let first_byte = slice[0];
-        if first_byte == 0 {
-            return Ok(SerializedStringResult {
-                bytes_read: offset,
-                value: "".to_string(),
-            });
-        } else if first_byte < 253 {
-            offset = 1;
-            length = first_byte as usize;
-        } else if first_byte == 253 {
-            length = slice_to_u16(&slice[1..3]).ok_or(IntegerParsingFailure)? as usize;
-            offset = 3;
-        } else if first_byte == 254 {
-            length = slice_to_u32(&slice[1..5]).ok_or(IntegerParsingFailure)? as usize;
-            offset = 5;
-        } else {
-            length = slice_to_u64(&slice[1..9]).ok_or(IntegerParsingFailure)? as usize;
-            offset = 9;
-        }
+    fn from_dogecoin_bytes(
+        slice: &[u8],
+    ) -> Result<SerializedStringResult, NetworkSerializationError> {
+        let result = <Vec<u8> as SerializeBytes>::from_dogecoin_bytes(slice)?;
+        let value = String::from_utf8(result.value)
+            .map_err(|_| NetworkSerializationError::StringParseError)?;
 
         Ok(SerializedStringResult {
-            bytes_read: offset + length,
-            value: slice_to_string(&slice[offset..offset + length]),
+            bytes_read: result.bytes_read,
+            value,
         })
     }
 
     fn to_dogecoin_bytes(
         &self,
     ) -> Result<Vec<u8>, CalculateSizeOfSerializedStringAndLengthBytesError> {
-        let mut buffer = Vec::new();
+        self.as_bytes().to_vec().to_dogecoin_bytes()
+    }
+}
 
-        let length = self.len();
+impl SerializeBytes for Vec<u8> {
+    fn from_dogecoin_bytes(
+        slice: &[u8],
+    ) -> Result<SerializedBytesResult, NetworkSerializationError> {
+        let mut reader = Reader::new(slice);
+        let value = reader.read_var_bytes()?;
 
-        buffer.reserve(calculate_size_of_serialized_string_and_length_bytes(
-            length,
+        Ok(SerializedBytesResult {
+            bytes_read: reader.offset(),
+            value,
+        })
+    }
+
+    fn to_dogecoin_bytes(
+        &self,
+    ) -> Result<Vec<u8>, CalculateSizeOfSerializedStringAndLengthBytesError> {
+        let mut buffer = Vec::with_capacity(calculate_size_of_serialized_string_and_length_bytes(
+            self.len(),
         )?);
 
-        if length < u8::MAX as usize {
-            let length = length as u8;
-            buffer.extend_from_slice(&length.to_le_bytes());
-            if length > 0 {
-                buffer.extend_from_slice(self.as_bytes());
-            }
-        } else if length < u16::MAX as usize {
-            let length = length as u16;
-            buffer.push(253);
-            buffer.extend_from_slice(&length.to_le_bytes());
-            buffer.extend_from_slice(self.as_bytes());
-        } else if length < u32::MAX as usize {
-            let length = length as u32;
-            buffer.extend_from_slice(&length.to_le_bytes());
-            buffer.extend_from_slice(self.as_bytes());
-            buffer.push(254);
-        } else if length < (u64::MAX - 1) as usize {
-            let length = length as u64;
-            buffer.push(255);
-            buffer.extend_from_slice(&length.to_le_bytes());
-            buffer.extend_from_slice(self.as_bytes());
-        } else {
-            return Err(CalculateSizeOfSerializedStringAndLengthBytesError::StringTooLong);
-        }
+        CompactSize(self.len() as u64).write(&mut buffer);
+        buffer.extend_from_slice(self);
 
         Ok(buffer)
     }
@@ -154,11 +505,15 @@ mod tests {
             calculate_size_of_serialized_string_and_length_bytes(u8::MAX as usize)
         );
         assert_eq!(
-            Ok(65540),
+            Ok(65538),
             calculate_size_of_serialized_string_and_length_bytes(u16::MAX as usize)
         );
         assert_eq!(
-            Ok(4294967304),
+            Ok(MAX_SIZE as usize + 5),
+            calculate_size_of_serialized_string_and_length_bytes(MAX_SIZE as usize)
+        );
+        assert_eq!(
+            Err(CalculateSizeOfSerializedStringAndLengthBytesError::StringTooLong),
             calculate_size_of_serialized_string_and_length_bytes(u32::MAX as usize)
         );
         assert_eq!(
@@ -168,21 +523,229 @@ mod tests {
     }
 
     #[test]
-    fn test_slice_to_string() {
-        let bytes: [u8; 4] = [0, 0, 0, 0];
-        assert_eq!("", slice_to_string(&bytes));
+    fn test_compact_size_round_trip() {
+        let cases: [(u64, &[u8]); 9] = [
+            (0, &[0x00]),
+            (252, &[0xFC]),
+            (253, &[0xFD, 0xFD, 0x00]),
+            (255, &[0xFD, 0xFF, 0x00]),
+            (0xFFFF, &[0xFD, 0xFF, 0xFF]),
+            (0x10000, &[0xFE, 0x00, 0x00, 0x01, 0x00]),
+            (0xFFFF_FFFF, &[0xFE, 0xFF, 0xFF, 0xFF, 0xFF]),
+            (
+                0x1_0000_0000,
+                &[0xFF, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+            ),
+            (
+                u64::MAX,
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+        ];
+
+        for (value, bytes) in cases {
+            assert_eq!(bytes, CompactSize(value).to_bytes().as_slice());
+            assert_eq!(bytes.len(), CompactSize(value).encoded_size());
+            assert_eq!(
+                Ok(CompactSizeResult {
+                    bytes_read: bytes.len(),
+                    value
+                }),
+                CompactSize::read_unchecked(bytes)
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_size_rejects_invalid() {
+        assert_eq!(
+            Err(CompactSizeError::NonCanonical(252)),
+            CompactSize::read(&[0xFD, 0xFC, 0x00])
+        );
+        assert_eq!(
+            Err(CompactSizeError::NonCanonical(0xFFFF)),
+            CompactSize::read(&[0xFE, 0xFF, 0xFF, 0x00, 0x00])
+        );
+        assert_eq!(
+            Err(CompactSizeError::NonCanonical(1)),
+            CompactSize::read_unchecked(&[0xFF, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            Err(CompactSizeError::TooLarge(MAX_SIZE + 1)),
+            CompactSize::read(&CompactSize(MAX_SIZE + 1).to_bytes())
+        );
+        assert_eq!(
+            Err(CompactSizeError::BufferTooShort),
+            CompactSize::read(&[0xFD, 0x00])
+        );
+        assert_eq!(
+            Err(CompactSizeError::BufferTooShort),
+            CompactSize::read(&[])
+        );
+    }
+
+    #[test]
+    fn test_reader() {
+        let bytes = [
+            0x01, 0x02, 0x03, 0x04, 0x1F, 0x90, 0x05, b'H', b'e', b'l', b'l', b'o', 0x01, 0xAA,
+        ];
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(0x04030201, reader.read_u32_le().unwrap());
+        assert_eq!(8080, reader.read_u16_be().unwrap());
+        assert_eq!(b"Hello", reader.read_var_str().unwrap().as_slice());
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(13, reader.offset());
+        assert_eq!(1, reader.remaining());
+
+        assert!(matches!(
+            reader.read_u16_le(),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 13,
+                needed: 2
+            })
+        ));
+        assert_eq!(&[0xAA], reader.read_remaining());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_reader_compact_vec_capped() {
+        let bytes = [0x02, 0x01, 0x00, 0x02, 0x00];
+        assert_eq!(
+            vec![1u16, 2],
+            Reader::new(&bytes).read_compact_vec_capped(2).unwrap()
+        );
+        assert!(matches!(
+            Reader::new(&bytes).read_compact_vec_capped::<u16>(1),
+            Err(NetworkSerializationError::TooManyEntries { count: 2, limit: 1 })
+        ));
+        assert!(matches!(
+            Reader::new(&bytes[..4]).read_compact_vec_capped::<u16>(2),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 3,
+                needed: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reader_truncated_var_str() {
+        let mut reader = Reader::new(&[0x00, 0xFE, 0x01]);
+        assert_eq!(0, reader.read_u8().unwrap());
+        assert!(matches!(
+            reader.read_var_str(),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 1,
+                needed: 5
+            })
+        ));
+
+        let mut reader = Reader::new(&[0x05, b'H', b'e']);
+        assert!(matches!(
+            reader.read_var_bytes(),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 1,
+                needed: 5
+            })
+        ));
+
+        let mut reader = Reader::new(&[0xFD, 0x01, 0x00]);
+        assert!(matches!(
+            reader.read_compact_size(),
+            Err(NetworkSerializationError::CompactSizeError(
+                CompactSizeError::NonCanonical(1)
+            ))
+        ));
+    }
+
+    #[derive(Debug, NetworkSerializable, PartialEq)]
+    struct WireSample {
+        protocol_version: u32,
+        #[wire(be)]
+        port: u16,
+        #[wire(compact_vec)]
+        hashes: Vec<[u8; 2]>,
+        #[wire(var_str, optional_since = 70001)]
+        name: Option<Vec<u8>>,
+    }
+
+    #[test]
+    fn test_derive_network_serializable() {
+        let bytes = [
+            0x71, 0x11, 0x01, 0x00, 0x1F, 0x90, 0x02, 0xAA, 0xBB, 0xCC, 0xDD, 0x02, b'o', b'k',
+        ];
+        let sample = WireSample::from_network_bytes(&bytes).unwrap();
+        assert_eq!(
+            WireSample {
+                protocol_version: 70001,
+                port: 8080,
+                hashes: vec![[0xAA, 0xBB], [0xCC, 0xDD]],
+                name: Some(b"ok".to_vec()),
+            },
+            sample
+        );
+        assert_eq!(&bytes, sample.to_network_bytes().unwrap().as_slice());
+
+        // Older peers leave the optional field out
+        let mut bytes = bytes[..11].to_vec();
+        bytes[0] = 0x70;
+        let sample = WireSample::from_network_bytes(&bytes).unwrap();
+        assert_eq!(70000, sample.protocol_version);
+        assert_eq!(None, sample.name);
+        assert_eq!(bytes, sample.to_network_bytes().unwrap());
+
+        assert!(matches!(
+            WireSample::from_network_bytes(&[0x71, 0x11, 0x01, 0x00, 0x1F, 0x90, 0x02, 0xAA]),
+            Err(NetworkSerializationError::BufferTooShort {
+                offset: 7,
+                needed: 2
+            })
+        ));
+    }
+
+    #[derive(Debug, NetworkSerializable, PartialEq)]
+    struct CappedSample {
+        #[wire(compact_vec, max = 2)]
+        items: Vec<u16>,
+    }
+
+    #[test]
+    fn test_derive_capped_compact_vec() {
+        let bytes = [0x02, 0x01, 0x00, 0x02, 0x00];
+        let sample = CappedSample::from_network_bytes(&bytes).unwrap();
+        assert_eq!(vec![1, 2], sample.items);
+        assert_eq!(&bytes, sample.to_network_bytes().unwrap().as_slice());
 
-        let bytes: [u8; 4] = [b'a', 0, 0, 0];
-        assert_eq!("a", slice_to_string(&bytes));
+        assert!(matches!(
+            CappedSample::from_network_bytes(&[0x03, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00]),
+            Err(NetworkSerializationError::TooManyEntries { count: 3, limit: 2 })
+        ));
+        assert!(matches!(
+            CappedSample {
+                items: vec![1, 2, 3]
+            }
+            .to_network_bytes(),
+            Err(NetworkSerializationError::TooManyEntries { count: 3, limit: 2 })
+        ));
+        assert!(matches!(
+            CappedSample::from_network_bytes(&[0x00, 0xFF]),
+            Err(NetworkSerializationError::UnknownBytes)
+        ));
+    }
 
-        let bytes: [u8; 4] = [b'a', b'b', b'c', b'd'];
-        assert_eq!("abcd", slice_to_string(&bytes));
+    #[test]
+    fn test_sanitize_string() {
+        assert_eq!(
+            "/Shibetoshi:1.14.6(linux; x64)/",
+            sanitize_string(b"/Shibetoshi:1.14.6(linux; x64)/")
+        );
+        assert_eq!("/evil31m:1.0/", sanitize_string(b"/evil\x1b[31m:1.0\n\0/"));
+        assert_eq!("/:/", sanitize_string("/\u{17e}\u{142}:\u{f3}/".as_bytes()));
     }
 
     #[test]
     fn test_serialize_string() {
         assert_eq!(
-            &[5, 'H' as u8, 'e' as u8, 'l' as u8, 'l' as u8, 'o' as u8],
+            &[5, b'H', b'e', b'l', b'l', b'o'],
             "Hello".to_string().to_dogecoin_bytes().unwrap().as_slice()
         );
 
@@ -216,12 +779,37 @@ mod tests {
     #[test]
     fn test_deserialize_string() {
         assert_eq!(
-            String::from_dogecoin_bytes(&[
-                5, 'H' as u8, 'e' as u8, 'l' as u8, 'l' as u8, 'o' as u8
-            ])
-            .unwrap()
-            .value,
+            String::from_dogecoin_bytes(&[5, b'H', b'e', b'l', b'l', b'o'])
+                .unwrap()
+                .value,
             "Hello"
         );
+
+        let result = String::from_dogecoin_bytes(&[0, 0xFF]).unwrap();
+        assert_eq!(1, result.bytes_read);
+        assert_eq!("", result.value);
+
+        assert!(String::from_dogecoin_bytes(&[]).is_err());
+        assert!(String::from_dogecoin_bytes(&[5, b'H', b'e']).is_err());
+    }
+
+    #[test]
+    fn test_serialize_bytes() {
+        let bytes = vec![0x00, 0x01, 0xFF];
+        let serialized = bytes.to_dogecoin_bytes().unwrap();
+        assert_eq!(&[3, 0x00, 0x01, 0xFF], serialized.as_slice());
+
+        let result = <Vec<u8> as SerializeBytes>::from_dogecoin_bytes(&serialized).unwrap();
+        assert_eq!(4, result.bytes_read);
+        assert_eq!(bytes, result.value);
+
+        let bytes = vec![0xAB; 0x10000];
+        let serialized = bytes.to_dogecoin_bytes().unwrap();
+        assert_eq!(&[0xFE, 0x00, 0x00, 0x01, 0x00, 0xAB], &serialized[..6]);
+        assert_eq!(0x10005, serialized.len());
+
+        let result = <Vec<u8> as SerializeBytes>::from_dogecoin_bytes(&serialized).unwrap();
+        assert_eq!(0x10005, result.bytes_read);
+        assert_eq!(bytes, result.value);
     }
 }
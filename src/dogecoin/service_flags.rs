@@ -0,0 +1,171 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use serializer::{Decode, Encode, Reader};
+
+// Services advertised in version and addr messages, see ServiceFlags in Core's protocol.h
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ServiceFlags(u64);
+
+const NAMED_FLAGS: [(ServiceFlags, &str); 5] = [
+    (ServiceFlags::NODE_NETWORK, "NETWORK"),
+    (ServiceFlags::NODE_GETUTXO, "GETUTXO"),
+    (ServiceFlags::NODE_BLOOM, "BLOOM"),
+    (ServiceFlags::NODE_WITNESS, "WITNESS"),
+    (ServiceFlags::NODE_NETWORK_LIMITED, "NETWORK_LIMITED"),
+];
+
+impl ServiceFlags {
+    pub const NONE: ServiceFlags = ServiceFlags(0);
+    pub const NODE_NETWORK: ServiceFlags = ServiceFlags(1 << 0);
+    pub const NODE_GETUTXO: ServiceFlags = ServiceFlags(1 << 1);
+    pub const NODE_BLOOM: ServiceFlags = ServiceFlags(1 << 2);
+    pub const NODE_WITNESS: ServiceFlags = ServiceFlags(1 << 3);
+    pub const NODE_NETWORK_LIMITED: ServiceFlags = ServiceFlags(1 << 10);
+
+    pub const fn from_bits(bits: u64) -> Self {
+        ServiceFlags(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: ServiceFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: ServiceFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: ServiceFlags) {
+        self.0 &= !other.0;
+    }
+
+    // Bits without a name in NAMED_FLAGS
+    pub fn unknown_bits(&self) -> u64 {
+        NAMED_FLAGS
+            .iter()
+            .fold(self.0, |bits, (flag, _)| bits & !flag.0)
+    }
+}
+
+impl From<u64> for ServiceFlags {
+    fn from(value: u64) -> Self {
+        ServiceFlags(value)
+    }
+}
+
+impl From<ServiceFlags> for u64 {
+    fn from(value: ServiceFlags) -> Self {
+        value.0
+    }
+}
+
+impl BitOr for ServiceFlags {
+    type Output = ServiceFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        ServiceFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for ServiceFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.insert(rhs);
+    }
+}
+
+impl fmt::Display for ServiceFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "NONE");
+        }
+
+        let mut names: Vec<String> = NAMED_FLAGS
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| name.to_string())
+            .collect();
+        let unknown_bits = self.unknown_bits();
+        if unknown_bits != 0 {
+            names.push(format!("UNKNOWN({:#x})", unknown_bits));
+        }
+
+        write!(f, "{}", names.join("|"))
+    }
+}
+
+impl Decode for ServiceFlags {
+    fn decode(reader: &mut Reader) -> Result<Self, NetworkSerializationError> {
+        Ok(ServiceFlags(reader.read_u64_le()?))
+    }
+}
+
+impl Encode for ServiceFlags {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), NetworkSerializationError> {
+        self.0.encode(buffer)
+    }
+}
+
+impl NetworkSerializable<ServiceFlags> for ServiceFlags {
+    fn from_network_bytes(bytes: &[u8]) -> Result<ServiceFlags, NetworkSerializationError> {
+        Reader::new(bytes).read()
+    }
+
+    fn to_network_bytes(&self) -> Result<Vec<u8>, NetworkSerializationError> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!("NONE", ServiceFlags::NONE.to_string());
+        assert_eq!(
+            "NETWORK|BLOOM",
+            (ServiceFlags::NODE_NETWORK | ServiceFlags::NODE_BLOOM).to_string()
+        );
+        assert_eq!(
+            "WITNESS|NETWORK_LIMITED",
+            ServiceFlags::from_bits(0x408).to_string()
+        );
+        assert_eq!(
+            "NETWORK|UNKNOWN(0x1000000)",
+            ServiceFlags::from_bits(0x1000001).to_string()
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut flags = ServiceFlags::NODE_NETWORK;
+        flags |= ServiceFlags::NODE_GETUTXO;
+        assert!(flags.contains(ServiceFlags::NODE_NETWORK));
+        assert!(flags.contains(ServiceFlags::NODE_GETUTXO));
+        assert!(!flags.contains(ServiceFlags::NODE_BLOOM));
+
+        flags.remove(ServiceFlags::NODE_NETWORK);
+        assert_eq!(ServiceFlags::NODE_GETUTXO, flags);
+        assert_eq!(0, flags.unknown_bits());
+        assert_eq!(0x80, ServiceFlags::from_bits(0x85).unknown_bits());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = [0x05, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80];
+        let flags = ServiceFlags::from_network_bytes(&bytes).unwrap();
+        assert_eq!(ServiceFlags::from_bits(0x8000_0000_0000_0405), flags);
+        assert_eq!(&bytes, flags.to_network_bytes().unwrap().as_slice());
+
+        assert!(ServiceFlags::from_network_bytes(&bytes[..7]).is_err());
+    }
+}
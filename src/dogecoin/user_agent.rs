@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::*;
+use messages::version::MAX_USER_AGENT_LENGTH;
+use serializer::{is_safe_char, sanitize_string};
+
+// BIP14 user agent, e.g. /Shibetoshi:1.14.6(linux; x64)/Crawler:0.1/
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserAgentComponent {
+    pub name: String,
+    pub version: Option<String>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserAgent {
+    pub components: Vec<UserAgentComponent>,
+}
+
+impl UserAgent {
+    // Rejects anything Core's SanitizeString would have to touch
+    pub fn parse(bytes: &[u8]) -> Result<Self, UserAgentError> {
+        if bytes.len() > MAX_USER_AGENT_LENGTH {
+            return Err(UserAgentError::TooLong(bytes.len()));
+        }
+        if let Some(offset) = bytes.iter().position(|byte| !is_safe_char(*byte)) {
+            return Err(UserAgentError::NonPrintable {
+                offset,
+                byte: bytes[offset],
+            });
+        }
+
+        // Only ASCII is left after the check above
+        parse_components(&String::from_utf8_lossy(bytes))
+    }
+
+    // Drops unsafe bytes first, the way Core builds cleanSubVer
+    pub fn parse_sanitized(bytes: &[u8]) -> Result<Self, UserAgentError> {
+        if bytes.len() > MAX_USER_AGENT_LENGTH {
+            return Err(UserAgentError::TooLong(bytes.len()));
+        }
+
+        parse_components(&sanitize_string(bytes))
+    }
+}
+
+impl FromStr for UserAgent {
+    type Err = UserAgentError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        UserAgent::parse(value.as_bytes())
+    }
+}
+
+impl fmt::Display for UserAgentComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, ":{}", version)?;
+        }
+        if !self.comments.is_empty() {
+            write!(f, "({})", self.comments.join("; "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for UserAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.components.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "/")?;
+        for component in &self.components {
+            write!(f, "{}/", component)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_components(value: &str) -> Result<UserAgent, UserAgentError> {
+    if value.is_empty() {
+        return Ok(UserAgent { components: vec![] });
+    }
+    let inner = value
+        .strip_prefix('/')
+        .and_then(|value| value.strip_suffix('/'))
+        .ok_or(UserAgentError::MissingSlashes)?;
+
+    // Comments may contain slashes of their own, only split outside of parentheses
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, character) in inner.char_indices() {
+        match character {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(UserAgentError::UnbalancedComment),
+            ')' => depth -= 1,
+            '/' if depth == 0 => {
+                segments.push(&inner[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(UserAgentError::UnbalancedComment);
+    }
+    segments.push(&inner[start..]);
+
+    let components = segments
+        .into_iter()
+        .map(parse_component)
+        .collect::<Result<_, _>>()?;
+
+    Ok(UserAgent { components })
+}
+
+fn parse_component(segment: &str) -> Result<UserAgentComponent, UserAgentError> {
+    let (head, comments) = match segment.find('(') {
+        Some(index) => {
+            let comments = segment[index + 1..]
+                .strip_suffix(')')
+                .ok_or(UserAgentError::UnbalancedComment)?;
+            (
+                &segment[..index],
+                comments.split(';').map(str::trim).collect(),
+            )
+        }
+        None => (segment, vec![]),
+    };
+
+    let (name, version) = match head.split_once(':') {
+        Some((name, version)) => (name, Some(version.to_string())),
+        None => (head, None),
+    };
+    if name.is_empty() {
+        return Err(UserAgentError::EmptyName);
+    }
+
+    Ok(UserAgentComponent {
+        name: name.to_string(),
+        version,
+        comments: comments.into_iter().map(str::to_string).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let user_agent: UserAgent = "/Shibetoshi:1.14.6(linux; x64)/Crawler:0.1/"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            vec![
+                UserAgentComponent {
+                    name: "Shibetoshi".to_string(),
+                    version: Some("1.14.6".to_string()),
+                    comments: vec!["linux".to_string(), "x64".to_string()],
+                },
+                UserAgentComponent {
+                    name: "Crawler".to_string(),
+                    version: Some("0.1".to_string()),
+                    comments: vec![],
+                },
+            ],
+            user_agent.components
+        );
+        assert_eq!(
+            "/Shibetoshi:1.14.6(linux; x64)/Crawler:0.1/",
+            user_agent.to_string()
+        );
+
+        let user_agent = UserAgent::parse(b"/bitcoinj(a/b)/").unwrap();
+        assert_eq!(None, user_agent.components[0].version);
+        assert_eq!(vec!["a/b".to_string()], user_agent.components[0].comments);
+
+        assert!(UserAgent::parse(b"").unwrap().components.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(
+            Err(UserAgentError::NonPrintable {
+                offset: 5,
+                byte: 0x1B
+            }),
+            UserAgent::parse(b"/evil\x1b[31m:1.0/")
+        );
+        assert_eq!(
+            Err(UserAgentError::MissingSlashes),
+            UserAgent::parse(b"Shibetoshi:1.14.6")
+        );
+        assert_eq!(Err(UserAgentError::EmptyName), UserAgent::parse(b"/:1.0/"));
+        assert_eq!(
+            Err(UserAgentError::UnbalancedComment),
+            UserAgent::parse(b"/Name:1.0(linux/")
+        );
+        assert_eq!(
+            Err(UserAgentError::TooLong(257)),
+            UserAgent::parse(&[b'a'; 257])
+        );
+
+        let user_agent = UserAgent::parse_sanitized(b"/evil\x1b[31m:1.0\n/").unwrap();
+        assert_eq!("/evil31m:1.0/", user_agent.to_string());
+    }
+}
@@ -0,0 +1,4 @@
+// Lets the derive macros refer to this crate by name from inside it
+extern crate self as dogecoin_handshaker;
+
+pub mod dogecoin;
@@ -1,42 +1,61 @@
 use std::error::Error;
 
-mod dogecoin;
-
-use dogecoin::NetworkType;
+use dogecoin_handshaker::dogecoin::{
+    self, node_connection_agent::NodeConnectionAgent, NetworkParams,
+};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
+    let outcome = match args.len() {
+        2 => {
+            let network = NetworkParams::from_name(&args[1]).ok_or("Unknown network")?;
+
+            eprintln!(
+                "Connecting to {} network through its DNS seeds",
+                network.name
+            );
+            let (agent, outcome) = NodeConnectionAgent::connect_to_network(network.clone())?;
+            eprintln!("Handshake with {} completed", agent.peer_address());
+            outcome
+        }
+        3 => {
+            let host = &args[1];
+            let port_str = &args[2];
+            let port = port_str.parse::<u16>()?;
+
+            eprintln!("Connecting to {}:{}", host, port);
+            let mut agent = NodeConnectionAgent::new(NetworkParams::TEST, host.as_str(), port)?;
+            eprintln!("Connection successful!\nPerforming handshake...");
+
+            agent.handshake()?
+        }
+        _ => {
+            eprintln!(
+                "Usage: {0} -- <IP or hostname> <port>\n       {0} -- <main|test|regtest>\nExample: cargo run -- 52.77.231.41 44556",
+                args[0]
+            );
+            return Err("Incorrect arguments".into());
+        }
+    };
+
+    eprintln!(
+        "Received version data from remote node: {:?}",
+        outcome.peer_version
+    );
+    eprintln!(
+        "Remote node services: {}",
+        outcome.peer_version.local_node_services
+    );
+    if let Some(client_name) = &outcome.peer_version.client_name {
         eprintln!(
-            "Usage: {} -- <IP> <port>\nExample: cargo run -- 52.77.231.41 44556",
-            args[0]
+            "Remote node user agent: {}",
+            dogecoin::serializer::sanitize_string(client_name)
         );
-        return Err("Incorrect arguments".into());
     }
-
-    let ip = &args[1];
-    let port_str = &args[2];
-    let port = port_str.parse::<u16>()?;
-
-    eprintln!("Connecting to {}:{}", ip, port);
-    let mut agent = dogecoin::node_connection_agent::NodeConnectionAgent::new(
-        NetworkType::Test,
-        ip.as_str(),
-        port,
-    )?;
-    eprintln!("Connection successful!\nSending version packet...");
-
-    agent.send_version()?;
-    eprintln!("Sending version packet success! Trying to receive node version packet");
-
-    let node_version = agent.receive_version()?;
-    eprintln!("Received version data from remote node: {:?}", node_version);
-    
-    agent.send_version_ack()?;
-    eprintln!("Sent version ack packet! Receiving version ack...");
-    
-    agent.read_version_ack()?;
-    eprintln!("Received version ack, success! Closing...");
+    eprintln!(
+        "Handshake successful! Negotiated protocol version {}, took {:?}. Closing...",
+        outcome.protocol_version, outcome.elapsed
+    );
 
     Ok(())
 }